@@ -0,0 +1,202 @@
+//! Derive macros for the parameter types of the `up2stream-uart` driver.
+//!
+//! Use them through the `derive` feature of `up2stream-uart`, which re-exports the macros next to
+//! the traits they implement.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, LitInt, Token, Type};
+
+/// Derive `ScalarParameter` for a tuple struct wrapping an `i8`.
+///
+/// The valid values are given with `#[range(min, max)]`, or `#[range(min, max, step = n)]` if not
+/// every value in between is valid. Besides the trait, a `new` constructor checking the range, a
+/// `new_const` constructor for constants and `FromStr` are generated.
+///
+/// # Example
+/// ```
+/// use up2stream_uart::ScalarParameter;
+///
+/// #[derive(Debug, PartialEq, Clone, Copy, ScalarParameter)]
+/// #[range(-10, 10)]
+/// pub struct Balance(i8);
+///
+/// let balance = Balance::new(-3).unwrap();
+/// assert_eq!(balance.saturating_add(-20), Balance::new(-10).unwrap());
+/// assert_eq!("7".parse::<Balance>().unwrap().get(), 7);
+///
+/// const CENTRE: Balance = Balance::new_const(0);
+/// ```
+#[proc_macro_derive(ScalarParameter, attributes(range))]
+pub fn derive_scalar_parameter(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match scalar_parameter(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+// The arguments of the `range` attribute
+struct Range {
+    min: i8,
+    max: i8,
+    step: Option<i8>,
+}
+
+// Parse an integer literal with an optional leading minus
+fn parse_i8(input: ParseStream) -> syn::Result<i8> {
+    let negative = input.parse::<Option<Token![-]>>()?.is_some();
+    let literal: LitInt = input.parse()?;
+    let value: i16 = literal.base10_parse()?;
+    let value = if negative { -value } else { value };
+
+    i8::try_from(value)
+        .map_err(|_| syn::Error::new(literal.span(), "the value must fit into an i8"))
+}
+
+impl Parse for Range {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let min = parse_i8(input)?;
+        input.parse::<Token![,]>()?;
+        let max = parse_i8(input)?;
+
+        let mut step = None;
+        if input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+            let name: Ident = input.parse()?;
+            if name != "step" {
+                return Err(syn::Error::new(name.span(), "expected `step = n`"));
+            }
+            input.parse::<Token![=]>()?;
+            step = Some(parse_i8(input)?);
+            input.parse::<Option<Token![,]>>()?;
+        }
+
+        Ok(Range { min, max, step })
+    }
+}
+
+fn scalar_parameter(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    let vis = &input.vis;
+
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "ScalarParameter cannot be derived for generic types",
+        ));
+    }
+
+    let field = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => &fields.unnamed[0],
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "expected a struct like `Name(i8)`",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "expected a struct like `Name(i8)`",
+            ))
+        }
+    };
+    let is_i8 =
+        matches!(&field.ty, Type::Path(path) if path.qself.is_none() && path.path.is_ident("i8"));
+    if !is_i8 {
+        return Err(syn::Error::new_spanned(
+            &field.ty,
+            "the wrapped value must be an i8",
+        ));
+    }
+
+    let mut attributes = input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("range"));
+    let attribute = attributes.next().ok_or_else(|| {
+        syn::Error::new(
+            Span::call_site(),
+            "the valid values must be given with `#[range(min, max)]`",
+        )
+    })?;
+    if let Some(duplicate) = attributes.next() {
+        return Err(syn::Error::new_spanned(
+            duplicate,
+            "duplicate `range` attribute",
+        ));
+    }
+    let Range { min, max, step } = attribute.parse_args()?;
+
+    if min > max {
+        return Err(syn::Error::new_spanned(
+            attribute,
+            "min must not be larger than max",
+        ));
+    }
+    let step_value = step.unwrap_or(1);
+    let step = match step {
+        Some(step) if step <= 0 => {
+            return Err(syn::Error::new_spanned(attribute, "step must be positive"))
+        }
+        Some(step) => quote! { const STEP: i8 = #step; },
+        None => quote! {},
+    };
+
+    let doc = format!(
+        "Create a new value between {min} and {max}.\n\n\
+         If the value is not valid then an `Error::OutOfRange` error is returned."
+    );
+
+    let const_doc = format!(
+        "Create a new value between {min} and {max} in a const context, panicking if it is not \
+         valid. Evaluated in a `const` item this is a compile error."
+    );
+    let panic = format!("{name} out of range");
+
+    Ok(quote! {
+        impl #name {
+            #[doc = #doc]
+            #vis fn new(value: i8) -> ::core::result::Result<Self, ::up2stream_uart::Error> {
+                <Self as ::up2stream_uart::ScalarParameter>::checked_new(value)
+            }
+
+            #[doc = #const_doc]
+            #vis const fn new_const(value: i8) -> Self {
+                let offset = value as i16 - #min as i16;
+                ::core::assert!(
+                    value >= #min && value <= #max && offset % #step_value as i16 == 0,
+                    #panic
+                );
+                Self(value)
+            }
+        }
+
+        impl ::up2stream_uart::ScalarParameter for #name {
+            const MIN: i8 = #min;
+            const MAX: i8 = #max;
+            #step
+
+            fn get(&self) -> i8 {
+                self.0
+            }
+
+            fn from_checked(value: ::up2stream_uart::CheckedValue<Self>) -> Self {
+                Self(value.get())
+            }
+        }
+
+        impl ::core::str::FromStr for #name {
+            type Err = ::up2stream_uart::Error;
+
+            fn from_str(s: &str) -> ::core::result::Result<Self, Self::Err> {
+                <Self as ::up2stream_uart::ScalarParameter>::from_parameter_str(s)
+            }
+        }
+    })
+}
@@ -0,0 +1,69 @@
+use std::str::FromStr;
+
+use up2stream_uart::{Error, ScalarParameter};
+
+/// A balance between the left and the right channel
+#[derive(Debug, PartialEq, Clone, Copy, ScalarParameter)]
+#[range(-10, 10)]
+pub struct Balance(i8);
+
+#[derive(Debug, PartialEq, Clone, Copy, ScalarParameter)]
+#[range(0, 100, step = 5)]
+struct MaxVolume(i8);
+
+#[test]
+fn new() {
+    assert_eq!(Balance::new(-10).unwrap().get(), -10);
+    assert_eq!(Balance::new(10).unwrap().get(), 10);
+    assert!(matches!(Balance::new(-11), Err(Error::OutOfRange)));
+    assert!(matches!(Balance::new(11), Err(Error::OutOfRange)));
+
+    assert_eq!(MaxVolume::new(85).unwrap().get(), 85);
+    assert!(matches!(MaxVolume::new(86), Err(Error::OutOfRange)));
+}
+
+#[test]
+fn range() {
+    assert_eq!((Balance::MIN, Balance::MAX, Balance::STEP), (-10, 10, 1));
+    assert_eq!(
+        (MaxVolume::MIN, MaxVolume::MAX, MaxVolume::STEP),
+        (0, 100, 5)
+    );
+
+    assert_eq!(Balance::iter_range().count(), 21);
+    assert_eq!(MaxVolume::iter_range().count(), 21);
+    assert_eq!(MaxVolume::saturating_new(42), MaxVolume(40));
+    assert_eq!(MaxVolume::from_percent(50), MaxVolume(50));
+}
+
+#[test]
+fn from_str() {
+    assert_eq!(Balance::from_str("-4").unwrap(), Balance(-4));
+    assert!(matches!(Balance::from_str("12"), Err(Error::OutOfRange)));
+    assert!(matches!(
+        Balance::from_str("left"),
+        Err(Error::InvalidString)
+    ));
+    assert!(matches!(MaxVolume::from_str("33"), Err(Error::OutOfRange)));
+}
+
+#[test]
+fn new_const() {
+    const LEFT: Balance = Balance::new_const(-10);
+    const LOUD: MaxVolume = MaxVolume::new_const(95);
+
+    assert_eq!(LEFT, Balance(-10));
+    assert_eq!(LOUD, MaxVolume(95));
+}
+
+#[test]
+#[should_panic]
+fn new_const_off_step() {
+    MaxVolume::new_const(std::hint::black_box(96));
+}
+
+#[test]
+fn parameter_str() {
+    let mut buf = [0; 3];
+    assert_eq!(Balance(-7).to_parameter_str(&mut buf), b"-7");
+}
@@ -0,0 +1,195 @@
+//! Configuration and construction of the driver.
+
+use embedded_hal::serial::Read;
+
+use crate::error::Error;
+use crate::serial::Transmit;
+use crate::{Up2Stream, DEFAULT_RESPONSE_SIZE};
+
+/// The default number of times a query is resent if no valid response is received.
+pub const DEFAULT_RETRIES: u8 = 3;
+
+/// The version of the UART API implemented by the board firmware.
+///
+/// Commands that need a newer version than the one configured, see [Command::protocol_version](crate::Command::protocol_version),
+/// are not sent and return [Error::NotSupportedForProtocolVersion].
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
+pub enum ProtocolVersion {
+    /// API version 3
+    #[default]
+    V3,
+    /// API version 4
+    V4,
+}
+
+/// Settings used by the driver when communicating with the board.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Config {
+    /// The number of consecutive polls of the reader returning `WouldBlock` after which a query
+    /// times out with [Error::Timeout]. This is a count of polls, not a time: how long it takes
+    /// depends on how fast the reader is polled. If `None` a single query waits indefinitely.
+    ///
+    /// Only used if [Config::timeout_ms] does not apply.
+    pub timeout_polls: Option<u32>,
+    /// The time in milliseconds without data from the reader after which a query times out with
+    /// [Error::Timeout]. It is measured with the clock given to [Up2StreamBuilder::timeout_ms],
+    /// without a clock [Config::timeout_polls] is used instead.
+    pub timeout_ms: Option<u32>,
+    /// The number of times a query is resent if it timed out or an ill formed response was received.
+    pub retries: u8,
+    /// The version of the UART API used by the board, which decides the commands that are sent.
+    pub protocol_version: ProtocolVersion,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            timeout_polls: None,
+            timeout_ms: None,
+            retries: DEFAULT_RETRIES,
+            protocol_version: ProtocolVersion::default(),
+        }
+    }
+}
+
+/// Builder for configuring and initialising an [Up2Stream] driver.
+///
+/// The const generic parameter `N` is the size of the response buffer of the driver, which
+/// can be changed with [Up2StreamBuilder::response_buffer].
+///
+/// # Example
+/// ```
+/// use up2stream_uart::{Up2StreamBuilder, ProtocolVersion};
+/// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+/// # fn main() -> Result<(), up2stream_uart::Error> {
+/// # let mut serial = SerialMock::new(&[
+/// #     SerialTransaction::write(b';'),
+/// #     SerialTransaction::write_many(b"VER;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::read_many(b"VER:1234-13-42;"),
+/// # ]);
+/// # let (reader, writer) = (serial.clone(), serial.clone());
+/// let mut up2stream_device = Up2StreamBuilder::new()
+///     .timeout_polls(100_000)
+///     .retries(2)
+///     .protocol_version(ProtocolVersion::V4)
+///     .check_ready(true)
+///     .response_buffer::<128>()
+///     .build(reader, writer)?;
+/// # serial.done();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Up2StreamBuilder<const N: usize = DEFAULT_RESPONSE_SIZE> {
+    config: Config,
+    clock: Option<fn() -> u32>,
+    wake_up: bool,
+    check_ready: bool,
+}
+
+impl Up2StreamBuilder {
+    /// Create a builder using the default [Config], that sends the wake-up
+    /// character and does not check if the board is ready.
+    pub fn new() -> Self {
+        Up2StreamBuilder {
+            config: Config::default(),
+            clock: None,
+            wake_up: true,
+            check_ready: false,
+        }
+    }
+}
+
+impl Default for Up2StreamBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Up2StreamBuilder<N> {
+    /// Use all the settings in `config`.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Set the number of consecutive polls of the reader returning `WouldBlock` after which a query
+    /// times out. The timeout is a count of polls, not a time, so choose it for the rate at which
+    /// the reader is polled, e.g. from the CPU clock and the time to receive a response.
+    pub fn timeout_polls(mut self, polls: u32) -> Self {
+        self.config.timeout_polls = Some(polls);
+        self
+    }
+
+    /// Set the time in milliseconds without data from the reader after which a query times out,
+    /// measured with `clock`, a [Clock](crate::Clock) returning the time in milliseconds, e.g. read
+    /// from a timer of the HAL. It takes precedence over [Up2StreamBuilder::timeout_polls].
+    pub fn timeout_ms(mut self, timeout_ms: u32, clock: fn() -> u32) -> Self {
+        self.config.timeout_ms = Some(timeout_ms);
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Set the number of times a query is resent.
+    pub fn retries(mut self, retries: u8) -> Self {
+        self.config.retries = retries;
+        self
+    }
+
+    /// Set the version of the UART API used by the board. Commands needing a newer version return
+    /// [Error::NotSupportedForProtocolVersion].
+    pub fn protocol_version(mut self, version: ProtocolVersion) -> Self {
+        self.config.protocol_version = version;
+        self
+    }
+
+    /// Set if the wake-up character `;`, which the board seems to require before usage, is sent.
+    pub fn wake_up(mut self, wake_up: bool) -> Self {
+        self.wake_up = wake_up;
+        self
+    }
+
+    /// Set if the firmware version is queried to check that the board is ready.
+    pub fn check_ready(mut self, check_ready: bool) -> Self {
+        self.check_ready = check_ready;
+        self
+    }
+
+    /// Change the size of the response buffer to `M` bytes.
+    pub fn response_buffer<const M: usize>(self) -> Up2StreamBuilder<M> {
+        Up2StreamBuilder {
+            config: self.config,
+            clock: self.clock,
+            wake_up: self.wake_up,
+            check_ready: self.check_ready,
+        }
+    }
+
+    /// Build the driver using the `reader` and `writer` halves of the UART, carrying out the
+    /// initialisation that has been configured.
+    ///
+    /// An error is returned if the wake-up character could not be sent or if the board
+    /// did not respond to the ready check.
+    pub fn build<R, W>(self, reader: R, writer: W) -> Result<Up2Stream<R, W, N>, Error>
+    where
+        R: Read<u8>,
+        W: Transmit<R>,
+    {
+        let mut driver = Up2Stream::from_config(reader, writer, self.config);
+
+        if let Some(clock) = self.clock {
+            driver.set_clock(clock);
+        }
+
+        if self.wake_up {
+            driver.wake_up()?;
+        }
+
+        if self.check_ready {
+            driver.firmware_version()?;
+        }
+
+        Ok(driver)
+    }
+}
@@ -0,0 +1,464 @@
+//! Shadow copy of the state of the board, see [Cached].
+
+use core::str::FromStr;
+
+use embedded_hal::serial::Read;
+
+use crate::clock::Clock;
+use crate::error::Error;
+use crate::serial::Transmit;
+use crate::{
+    Bass, DeviceStatus, Source, Switch, Treble, Up2Stream, Volume, COMMAND_BAS, COMMAND_LED,
+    COMMAND_MUT, COMMAND_PLA, COMMAND_SRC, COMMAND_TRE, COMMAND_VOL, DEFAULT_RESPONSE_SIZE,
+};
+
+/// The error returned by [Cached::poll_notifications], with the number of notifications that
+/// were applied before it.
+#[derive(Debug)]
+pub struct NotificationError {
+    pub applied: usize,
+    pub error: Error,
+}
+
+/// A driver in cached mode, which keeps a shadow copy of the [DeviceStatus] of the board.
+///
+/// The getters return the value of the shadow copy without communicating with the board, unless the
+/// copy is older than the freshness limit, in which case the status is fetched again with `STA`.
+/// Successful setters and the notifications sent by the board when it is changed by other means
+/// (e.g. the remote control), see [Cached::poll_notifications], update the shadow copy.
+///
+/// # Example
+/// ```
+/// use up2stream_uart::{Cached, Up2Stream, Volume};
+/// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+/// # let mut serial = SerialMock::new(&[
+/// #     SerialTransaction::write(b';'),
+/// #     SerialTransaction::write_many(b"STA;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::read_many(b"STA:NET,0,30,0,0,1,1,1,1,0;"),
+/// #     SerialTransaction::write_many(b"VOL:20;"),
+/// #     SerialTransaction::flush(),
+/// # ]);
+/// # let mut timer = 0;
+/// let clock = || { timer += 1; timer };
+///
+/// // Fetch the status again if it is older than 5 s
+/// let mut up2stream_device = Cached::new(Up2Stream::new_combined(serial.clone()), clock, 5_000);
+///
+/// // Fetches the status of the board
+/// assert!(!up2stream_device.mute_status().unwrap());
+///
+/// // Returned without communicating with the board
+/// up2stream_device.set_volume(Volume::new(20).unwrap()).unwrap();
+/// assert_eq!(up2stream_device.volume().unwrap(), Volume::new(20).unwrap());
+/// # serial.done();
+/// ```
+pub struct Cached<R, W, C, const N: usize = DEFAULT_RESPONSE_SIZE>
+where
+    R: Read<u8>,
+    W: Transmit<R>,
+{
+    driver: Up2Stream<R, W, N>,
+    clock: C,
+    max_age_ms: u32,
+    shadow: Option<Shadow>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Shadow {
+    status: DeviceStatus,
+    fetched_ms: u32,
+}
+
+impl<R, W, C, const N: usize> Cached<R, W, C, N>
+where
+    R: Read<u8>,
+    W: Transmit<R>,
+    C: Clock,
+{
+    /// Use the `driver` in cached mode. The status is fetched again when the shadow copy
+    /// is older than `max_age_ms` milliseconds according to `clock`.
+    pub fn new(driver: Up2Stream<R, W, N>, clock: C, max_age_ms: u32) -> Self {
+        Cached {
+            driver,
+            clock,
+            max_age_ms,
+            shadow: None,
+        }
+    }
+
+    /// The driver, for methods that are not cached. Changes made with it are not reflected in the
+    /// shadow copy until it is fetched again, see [Cached::invalidate].
+    pub fn driver(&mut self) -> &mut Up2Stream<R, W, N> {
+        &mut self.driver
+    }
+
+    /// Give back the driver and the clock.
+    pub fn release(self) -> (Up2Stream<R, W, N>, C) {
+        (self.driver, self.clock)
+    }
+
+    /// Discard the shadow copy, so that the next getter fetches the status.
+    pub fn invalidate(&mut self) {
+        self.shadow = None;
+    }
+
+    /// Fetch the status from the board, whatever the age of the shadow copy.
+    pub fn refresh(&mut self) -> Result<DeviceStatus, Error> {
+        let status = self.driver.status()?;
+        self.shadow = Some(Shadow {
+            status,
+            fetched_ms: self.clock.now_ms(),
+        });
+        Ok(status)
+    }
+
+    /// Get the device status, see [Up2Stream::status].
+    pub fn status(&mut self) -> Result<DeviceStatus, Error> {
+        let now = self.clock.now_ms();
+        match self.shadow {
+            Some(shadow) if now.wrapping_sub(shadow.fetched_ms) < self.max_age_ms => {
+                Ok(shadow.status)
+            }
+            _ => self.refresh(),
+        }
+    }
+
+    /// Get the input source, see [Up2Stream::input_source].
+    pub fn input_source(&mut self) -> Result<Source, Error> {
+        self.status().map(|status| status.source)
+    }
+
+    /// Get the volume, see [Up2Stream::volume].
+    pub fn volume(&mut self) -> Result<Volume, Error> {
+        self.status().map(|status| status.volume)
+    }
+
+    /// Get if the audio is muted, see [Up2Stream::mute_status].
+    pub fn mute_status(&mut self) -> Result<bool, Error> {
+        self.status().map(|status| status.mute)
+    }
+
+    /// Get the bass, see [Up2Stream::bass].
+    pub fn bass(&mut self) -> Result<Bass, Error> {
+        self.status().map(|status| status.bass)
+    }
+
+    /// Get the treble, see [Up2Stream::treble].
+    pub fn treble(&mut self) -> Result<Treble, Error> {
+        self.status().map(|status| status.treble)
+    }
+
+    /// Select the input source, see [Up2Stream::select_input_source].
+    pub fn select_input_source(&mut self, source: Source) -> Result<(), Error> {
+        self.driver.select_input_source(source)?;
+        self.update(|status| status.source = source);
+        Ok(())
+    }
+
+    /// Set the volume, see [Up2Stream::set_volume].
+    pub fn set_volume(&mut self, volume: Volume) -> Result<(), Error> {
+        self.driver.set_volume(volume)?;
+        self.update(|status| status.volume = volume);
+        Ok(())
+    }
+
+    /// Mute, unmute or toggle muting, see [Up2Stream::set_mute].
+    pub fn set_mute(&mut self, switch: Switch) -> Result<(), Error> {
+        self.driver.set_mute(switch)?;
+        self.update(|status| {
+            status.mute = match switch {
+                Switch::On => true,
+                Switch::Off => false,
+                Switch::Toggle => !status.mute,
+            }
+        });
+        Ok(())
+    }
+
+    /// Set the bass, see [Up2Stream::set_bass].
+    pub fn set_bass(&mut self, bass: Bass) -> Result<(), Error> {
+        self.driver.set_bass(bass)?;
+        self.update(|status| status.bass = bass);
+        Ok(())
+    }
+
+    /// Set the treble, see [Up2Stream::set_treble].
+    pub fn set_treble(&mut self, treble: Treble) -> Result<(), Error> {
+        self.driver.set_treble(treble)?;
+        self.update(|status| status.treble = treble);
+        Ok(())
+    }
+
+    /// Toggle between play and pause, see [Up2Stream::play_pause_toggle].
+    pub fn play_pause_toggle(&mut self) -> Result<(), Error> {
+        self.driver.play_pause_toggle()?;
+        self.update(|status| status.playing = !status.playing);
+        Ok(())
+    }
+
+    /// Stop playback, see [Up2Stream::stop].
+    pub fn stop(&mut self) -> Result<(), Error> {
+        self.driver.stop()?;
+        self.update(|status| status.playing = false);
+        Ok(())
+    }
+
+    /// Read the notifications sent by the board when its state is changed by other means, such as
+    /// `VOL:25;` after the volume knob has been turned, and update the shadow copy. Returns the
+    /// number of notifications applied.
+    ///
+    /// This does not wait for data and should be called regularly, as notifications arriving
+    /// during a query are discarded. A notification that has only partially arrived is kept and
+    /// completed by the next call.
+    ///
+    /// Notifications of commands that are not part of the [DeviceStatus] are skipped. If reading
+    /// fails, or a notification has an invalid parameter, polling stops at the bad frame and the
+    /// error is returned in a [NotificationError] together with the number of notifications
+    /// applied before it, which are kept.
+    pub fn poll_notifications(&mut self) -> Result<usize, NotificationError> {
+        let mut applied = 0;
+
+        loop {
+            let command = match self.driver.read_frame(false) {
+                Ok(command) => command,
+                Err(Error::Timeout) => return Ok(applied),
+                Err(error) => return Err(NotificationError { applied, error }),
+            };
+
+            let Some(shadow) = self.shadow.as_mut() else {
+                continue;
+            };
+            let status = &mut shadow.status;
+            let parameter = self.driver.response.as_str();
+            let switch = |parameter| Switch::from_str(parameter).and_then(|s| s.to_bool());
+
+            let result = match command.as_str() {
+                COMMAND_VOL => Volume::from_str(parameter).map(|v| status.volume = v),
+                COMMAND_MUT => switch(parameter).map(|m| status.mute = m),
+                COMMAND_BAS => Bass::from_str(parameter).map(|b| status.bass = b),
+                COMMAND_TRE => Treble::from_str(parameter).map(|t| status.treble = t),
+                COMMAND_SRC => Source::from_str(parameter).map(|s| status.source = s),
+                COMMAND_PLA => switch(parameter).map(|p| status.playing = p),
+                COMMAND_LED => switch(parameter).map(|l| status.led = l),
+                _ => continue,
+            };
+
+            match result {
+                Ok(()) => applied += 1,
+                Err(error) => return Err(NotificationError { applied, error }),
+            }
+        }
+    }
+
+    fn update(&mut self, change: impl FnOnce(&mut DeviceStatus)) {
+        if let Some(shadow) = self.shadow.as_mut() {
+            change(&mut shadow.status);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::cell::Cell;
+
+    use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+    use embedded_hal_mock::MockError;
+
+    use crate::sim::Simulator;
+    use crate::{Combined, Up2StreamBuilder};
+
+    #[test]
+    fn getters_use_shadow_copy() {
+        let expectations = [
+            SerialTransaction::write(b';'),
+            SerialTransaction::write_many(b"STA;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"STA:NET,0,30,-2,4,1,1,1,1,0;"),
+            SerialTransaction::write_many(b"VOL:20;"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(b"MUT:T;"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(b"SRC:BT;"),
+            SerialTransaction::flush(),
+        ];
+
+        let mut serial = SerialMock::new(&expectations);
+        let time = Cell::new(0);
+
+        let mut up2stream_device = Cached::new(
+            Up2Stream::new(serial.clone(), serial.clone()),
+            || time.get(),
+            1000,
+        );
+
+        assert_eq!(up2stream_device.bass().unwrap(), Bass::new(4).unwrap());
+        time.set(999);
+        assert_eq!(up2stream_device.treble().unwrap(), Treble::new(-2).unwrap());
+
+        up2stream_device
+            .set_volume(Volume::new(20).unwrap())
+            .unwrap();
+        up2stream_device.set_mute(Switch::Toggle).unwrap();
+        up2stream_device
+            .select_input_source(Source::Bluetooth)
+            .unwrap();
+
+        let status = up2stream_device.status().unwrap();
+        assert_eq!(status.volume, Volume::new(20).unwrap());
+        assert!(status.mute);
+        assert_eq!(status.source, Source::Bluetooth);
+
+        serial.done();
+    }
+
+    #[test]
+    fn status_fetched_when_stale() {
+        let expectations = [
+            SerialTransaction::write(b';'),
+            SerialTransaction::write_many(b"STA;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"STA:NET,0,30,-2,4,1,1,1,1,0;"),
+            SerialTransaction::write_many(b"STA;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"STA:USB,1,45,-2,4,1,1,0,1,0;"),
+        ];
+
+        let mut serial = SerialMock::new(&expectations);
+        let time = Cell::new(u32::MAX - 10);
+
+        let mut up2stream_device = Cached::new(
+            Up2Stream::new(serial.clone(), serial.clone()),
+            || time.get(),
+            100,
+        );
+
+        assert_eq!(up2stream_device.volume().unwrap(), Volume::new(30).unwrap());
+        // The clock wraps around
+        time.set(88);
+        assert_eq!(up2stream_device.volume().unwrap(), Volume::new(30).unwrap());
+        time.set(89);
+        assert_eq!(up2stream_device.volume().unwrap(), Volume::new(45).unwrap());
+        assert_eq!(up2stream_device.input_source().unwrap(), Source::Usb);
+
+        serial.done();
+    }
+
+    #[test]
+    fn setter_failure_leaves_shadow_copy() {
+        let mut simulator = Simulator::new();
+        simulator.state_mut().volume = Volume::new(10).unwrap();
+
+        let driver = Up2StreamBuilder::new().build(simulator, Combined).unwrap();
+        let mut up2stream_device = Cached::new(driver, || 0, 1000);
+
+        assert_eq!(up2stream_device.volume().unwrap(), Volume::new(10).unwrap());
+
+        // Stop is not supported for the line in
+        up2stream_device
+            .select_input_source(Source::LineIn)
+            .unwrap();
+        up2stream_device.play_pause_toggle().unwrap();
+        assert!(up2stream_device.stop().is_err());
+        assert!(up2stream_device.status().unwrap().playing);
+
+        let (driver, _) = up2stream_device.release();
+        let (simulator, _) = driver.release();
+        assert_eq!(simulator.state().source, Source::LineIn);
+    }
+
+    #[test]
+    fn notifications_update_shadow_copy() {
+        let expectations = [
+            SerialTransaction::write(b';'),
+            SerialTransaction::write_many(b"STA;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"STA:NET,0,30,-2,4,1,1,1,1,0;"),
+            SerialTransaction::read_many(b"VOL:25;\r\nMUT:1;LED:0;XYZ:1;BAS:11;"),
+            SerialTransaction::read_error(nb::Error::WouldBlock),
+            SerialTransaction::read_many(b"TRE:"),
+            SerialTransaction::read_error(nb::Error::WouldBlock),
+            SerialTransaction::read_many(b"-3;BAS:1"),
+            SerialTransaction::read_error(nb::Error::WouldBlock),
+            SerialTransaction::read_many(b"0;"),
+            SerialTransaction::read_error(nb::Error::WouldBlock),
+        ];
+
+        let mut serial = SerialMock::new(&expectations);
+
+        let mut up2stream_device =
+            Cached::new(Up2Stream::new(serial.clone(), serial.clone()), || 0, 1000);
+
+        up2stream_device.status().unwrap();
+
+        assert!(matches!(
+            up2stream_device.poll_notifications(),
+            Err(NotificationError {
+                applied: 3,
+                error: Error::OutOfRange
+            })
+        ));
+        assert_eq!(up2stream_device.poll_notifications().unwrap(), 0);
+
+        // A partially received notification is completed by the next call
+        assert_eq!(up2stream_device.poll_notifications().unwrap(), 0);
+        assert_eq!(up2stream_device.poll_notifications().unwrap(), 1);
+        assert_eq!(up2stream_device.poll_notifications().unwrap(), 1);
+
+        let status = up2stream_device.status().unwrap();
+        assert_eq!(status.volume, Volume::new(25).unwrap());
+        assert!(status.mute);
+        assert!(!status.led);
+        assert_eq!(status.bass, Bass::new(10).unwrap());
+        assert_eq!(status.treble, Treble::new(-3).unwrap());
+
+        serial.done();
+    }
+
+    #[test]
+    fn notifications_counted_before_read_error() {
+        let read_error = || {
+            SerialTransaction::read_error(nb::Error::Other(MockError::Io(
+                std::io::ErrorKind::Other,
+            )))
+        };
+        let expectations = [
+            SerialTransaction::write(b';'),
+            SerialTransaction::write_many(b"STA;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"STA:NET,0,30,-2,4,1,1,1,1,0;"),
+            SerialTransaction::read_many(b"VOL:25;MUT:1;"),
+            read_error(),
+            read_error(),
+        ];
+
+        let mut serial = SerialMock::new(&expectations);
+
+        let mut up2stream_device =
+            Cached::new(Up2Stream::new(serial.clone(), serial.clone()), || 0, 1000);
+
+        up2stream_device.status().unwrap();
+
+        assert!(matches!(
+            up2stream_device.poll_notifications(),
+            Err(NotificationError {
+                applied: 2,
+                error: Error::Read
+            })
+        ));
+        assert!(matches!(
+            up2stream_device.poll_notifications(),
+            Err(NotificationError {
+                applied: 0,
+                error: Error::Read
+            })
+        ));
+
+        let status = up2stream_device.status().unwrap();
+        assert_eq!(status.volume, Volume::new(25).unwrap());
+        assert!(status.mute);
+
+        serial.done();
+    }
+}
@@ -0,0 +1,19 @@
+//! Source of time used by the driver and its utilities.
+
+/// A monotonic clock counting milliseconds.
+///
+/// It is implemented for closures returning the time in milliseconds, so that a timer of the HAL
+/// can be used without a wrapper type, e.g. `|| (timer.get_counter().ticks() / 1000) as u32`.
+pub trait Clock {
+    /// The number of milliseconds since an arbitrary starting point, wrapping on overflow.
+    fn now_ms(&mut self) -> u32;
+}
+
+impl<F> Clock for F
+where
+    F: FnMut() -> u32,
+{
+    fn now_ms(&mut self) -> u32 {
+        self()
+    }
+}
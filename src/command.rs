@@ -0,0 +1,310 @@
+//! Commands to the board as data, see [Command].
+
+use core::str::FromStr;
+
+use crate::error::Error;
+use crate::{
+    AudioChannel, Bass, Led, LoopMode, ProtocolVersion, ScalarParameter, Source, Switch,
+    SystemControl, Treble, Volume, COMMAND_AUD, COMMAND_BAS, COMMAND_BEP, COMMAND_BTC, COMMAND_CHN,
+    COMMAND_LED, COMMAND_LPM, COMMAND_MUT, COMMAND_NXT, COMMAND_POP, COMMAND_PRE, COMMAND_SRC,
+    COMMAND_STP, COMMAND_SYSTEM_CONTROL, COMMAND_TRE, COMMAND_VBS, COMMAND_VOL,
+    PARAMETER_DELIMITER, PARAMETER_START, TERMINATOR,
+};
+
+/// The maximum length in bytes of an encoded [Command].
+pub const MAX_ENCODED_LENGTH: usize = 24;
+
+// Longest parameter of a command
+pub(crate) const MAX_PARAMETER_LENGTH: usize = 16;
+
+/// A command to the board, with a typed parameter.
+///
+/// Commands can be stored, logged or queued as data and are sent with [Up2Stream::send](crate::Up2Stream::send).
+/// [encode] converts a command into the frame sent over the UART and [Command::from_str] converts
+/// the frame back into a command.
+///
+/// # Example
+/// ```
+/// use up2stream_uart::{encode, Command, Source, Volume};
+///
+/// let commands = [
+///     Command::SelectSource(Source::LineIn),
+///     Command::SetVolume(Volume::new(30).unwrap()),
+/// ];
+///
+/// let mut frame = [0; up2stream_uart::MAX_ENCODED_LENGTH];
+/// let length = encode(&commands[1], &mut frame).unwrap();
+/// assert_eq!(&frame[..length], b"VOL:30;");
+///
+/// assert_eq!("SRC:LINE-IN;".parse::<Command>().unwrap(), commands[0]);
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Command {
+    /// Reboot, enter standby, factory reset or recover
+    SystemControl(SystemControl),
+    /// Enable or disable the audio output
+    SetAudioOut(bool),
+    /// Select the input source
+    SelectSource(Source),
+    /// Set the volume
+    SetVolume(Volume),
+    /// Mute, unmute or toggle muting of the audio
+    SetMute(Switch),
+    /// Set the bass
+    SetBass(Bass),
+    /// Set the treble
+    SetTreble(Treble),
+    /// Toggle between play and pause
+    PlayPause,
+    /// Stop playback
+    Stop,
+    /// Skip to the next track
+    NextTrack,
+    /// Go to the previous track
+    PreviousTrack,
+    /// Reconnect the last bluetooth device
+    ConnectBluetooth,
+    /// Disconnect the bluetooth device
+    DisconnectBluetooth,
+    /// Switch the LEDs on, off or toggle them
+    SetLed(Led),
+    /// Enable or disable the beep sound
+    SetBeep(bool),
+    /// Enable, disable or toggle virtual bass
+    SetVirtualBass(Switch),
+    /// Set the loop mode for network playback
+    SetLoopMode(LoopMode),
+    /// Select the audio channel that is output
+    SetAudioChannel(AudioChannel),
+}
+
+impl Command {
+    /// The name of the command, e.g. `VOL`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::SystemControl(_) => COMMAND_SYSTEM_CONTROL,
+            Self::SetAudioOut(_) => COMMAND_AUD,
+            Self::SelectSource(_) => COMMAND_SRC,
+            Self::SetVolume(_) => COMMAND_VOL,
+            Self::SetMute(_) => COMMAND_MUT,
+            Self::SetBass(_) => COMMAND_BAS,
+            Self::SetTreble(_) => COMMAND_TRE,
+            Self::PlayPause => COMMAND_POP,
+            Self::Stop => COMMAND_STP,
+            Self::NextTrack => COMMAND_NXT,
+            Self::PreviousTrack => COMMAND_PRE,
+            Self::ConnectBluetooth | Self::DisconnectBluetooth => COMMAND_BTC,
+            Self::SetLed(_) => COMMAND_LED,
+            Self::SetBeep(_) => COMMAND_BEP,
+            Self::SetVirtualBass(_) => COMMAND_VBS,
+            Self::SetLoopMode(_) => COMMAND_LPM,
+            Self::SetAudioChannel(_) => COMMAND_CHN,
+        }
+    }
+
+    /// The oldest version of the UART API that supports the command.
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        match self {
+            Self::SystemControl(SystemControl::Recover) => ProtocolVersion::V4,
+            _ => ProtocolVersion::V3,
+        }
+    }
+
+    /// The parameter of the command, empty if the command has none.
+    pub(crate) fn parameter<'a>(&self, buf: &'a mut [u8; MAX_PARAMETER_LENGTH]) -> &'a [u8] {
+        match self {
+            Self::SystemControl(control) => control.to_parameter_str(buf),
+            Self::SetAudioOut(enable) => Switch::from(*enable).to_parameter_str(buf),
+            Self::SelectSource(source) => source.to_parameter_str(buf),
+            Self::SetVolume(volume) => volume.to_parameter_str(buf),
+            Self::SetMute(switch) => switch.to_parameter_str(buf),
+            Self::SetBass(bass) => bass.to_parameter_str(buf),
+            Self::SetTreble(treble) => treble.to_parameter_str(buf),
+            Self::ConnectBluetooth => Switch::On.to_parameter_str(buf),
+            Self::DisconnectBluetooth => Switch::Off.to_parameter_str(buf),
+            Self::SetLed(led) => led.to_parameter_str(buf),
+            Self::SetBeep(enable) => Switch::from(*enable).to_parameter_str(buf),
+            Self::SetVirtualBass(switch) => switch.to_parameter_str(buf),
+            Self::SetLoopMode(loop_mode) => loop_mode.to_parameter_str(buf),
+            Self::SetAudioChannel(channel) => channel.to_parameter_str(buf),
+            Self::PlayPause | Self::Stop | Self::NextTrack | Self::PreviousTrack => &buf[..0],
+        }
+    }
+}
+
+/// Encode the `command` into `buffer` as the frame sent to the board, e.g. `VOL:30;`, returning
+/// the length of the frame.
+///
+/// Returns [Error::BufferOverflow] if the frame does not fit into `buffer`. A buffer of
+/// [MAX_ENCODED_LENGTH] bytes is large enough for every command.
+pub fn encode(command: &Command, buffer: &mut [u8]) -> Result<usize, Error> {
+    let mut parameter_buf = [0; MAX_PARAMETER_LENGTH];
+    let parameter = command.parameter(&mut parameter_buf);
+
+    encode_frame(command.name().as_bytes(), [parameter], buffer)
+}
+
+// Encode a frame into `buffer`, returning its length. Frames have the following syntax (BNF):
+//
+//    <frame> ::= <command_name> <terminator> | <command_name> ":" <parameter_list> <terminator>
+//    <parameter_list> ::= <parameter> | <parameter> "," <parameter_list>
+//    <terminator> ::= ";"
+//
+// Empty parameters are skipped, so a command without parameters is encoded like a query.
+pub(crate) fn encode_frame<'a>(
+    name: &[u8],
+    parameters: impl IntoIterator<Item = &'a [u8]>,
+    buffer: &mut [u8],
+) -> Result<usize, Error> {
+    let mut length = 0;
+    let mut push = |bytes: &[u8]| {
+        let end = length + bytes.len();
+        buffer
+            .get_mut(length..end)
+            .ok_or(Error::BufferOverflow)?
+            .copy_from_slice(bytes);
+        length = end;
+        Ok::<(), Error>(())
+    };
+
+    push(name)?;
+    let mut separator = PARAMETER_START;
+    for parameter in parameters
+        .into_iter()
+        .filter(|parameter| !parameter.is_empty())
+    {
+        push(&[separator])?;
+        push(parameter)?;
+        separator = PARAMETER_DELIMITER;
+    }
+    push(&[TERMINATOR])?;
+
+    Ok(length)
+}
+
+/// Parses a frame such as `VOL:30;`. The terminator is optional.
+impl FromStr for Command {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.strip_suffix(TERMINATOR as char).unwrap_or(s);
+        let (name, parameter) = s.split_once(PARAMETER_START as char).unwrap_or((s, ""));
+
+        let command = match (name, parameter) {
+            (COMMAND_SYSTEM_CONTROL, p) => Self::SystemControl(SystemControl::from_str(p)?),
+            (COMMAND_AUD, p) => Self::SetAudioOut(Switch::from_str(p)?.to_bool()?),
+            (COMMAND_SRC, p) => Self::SelectSource(Source::from_str(p)?),
+            (COMMAND_VOL, p) => Self::SetVolume(Volume::from_str(p)?),
+            (COMMAND_MUT, p) => Self::SetMute(Switch::from_str(p)?),
+            (COMMAND_BAS, p) => Self::SetBass(Bass::from_str(p)?),
+            (COMMAND_TRE, p) => Self::SetTreble(Treble::from_str(p)?),
+            (COMMAND_POP, "") => Self::PlayPause,
+            (COMMAND_STP, "") => Self::Stop,
+            (COMMAND_NXT, "") => Self::NextTrack,
+            (COMMAND_PRE, "") => Self::PreviousTrack,
+            (COMMAND_BTC, "1") => Self::ConnectBluetooth,
+            (COMMAND_BTC, "0") => Self::DisconnectBluetooth,
+            (COMMAND_LED, p) => Self::SetLed(Led::from_str(p)?),
+            (COMMAND_BEP, p) => Self::SetBeep(Switch::from_str(p)?.to_bool()?),
+            (COMMAND_VBS, p) => Self::SetVirtualBass(Switch::from_str(p)?),
+            (COMMAND_LPM, p) => Self::SetLoopMode(LoopMode::from_str(p)?),
+            (COMMAND_CHN, p) => Self::SetAudioChannel(AudioChannel::from_str(p)?),
+            _ => return Err(Error::InvalidString),
+        };
+
+        Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn all_commands() -> [Command; 22] {
+        [
+            Command::SystemControl(SystemControl::Reboot),
+            Command::SystemControl(SystemControl::Standby),
+            Command::SetAudioOut(true),
+            Command::SetAudioOut(false),
+            Command::SelectSource(Source::LineIn),
+            Command::SetVolume(Volume::new(100).unwrap()),
+            Command::SetMute(Switch::Toggle),
+            Command::SetBass(Bass::new(-10).unwrap()),
+            Command::SetTreble(Treble::new(3).unwrap()),
+            Command::PlayPause,
+            Command::Stop,
+            Command::NextTrack,
+            Command::PreviousTrack,
+            Command::ConnectBluetooth,
+            Command::DisconnectBluetooth,
+            Command::SelectSource(Source::Bluetooth),
+            Command::SetVolume(Volume::new(0).unwrap()),
+            Command::SetLed(Led::Off),
+            Command::SetBeep(false),
+            Command::SetVirtualBass(Switch::Toggle),
+            Command::SetLoopMode(LoopMode::RepeatShuffle),
+            Command::SetAudioChannel(AudioChannel::Left),
+        ]
+    }
+
+    #[test]
+    fn encode_commands() {
+        let expected: [&[u8]; 22] = [
+            b"SYS:REBOOT;",
+            b"SYS:STANDBY;",
+            b"AUD:1;",
+            b"AUD:0;",
+            b"SRC:LINE-IN;",
+            b"VOL:100;",
+            b"MUT:T;",
+            b"BAS:-10;",
+            b"TRE:3;",
+            b"POP;",
+            b"STP;",
+            b"NXT;",
+            b"PRE;",
+            b"BTC:1;",
+            b"BTC:0;",
+            b"SRC:BT;",
+            b"VOL:0;",
+            b"LED:0;",
+            b"BEP:0;",
+            b"VBS:T;",
+            b"LPM:REPEATSHUFFLE;",
+            b"CHN:L;",
+        ];
+
+        for (command, expected) in all_commands().iter().zip(expected) {
+            let mut frame = [0; MAX_ENCODED_LENGTH];
+            let length = encode(command, &mut frame).unwrap();
+            assert_eq!(&frame[..length], expected);
+        }
+    }
+
+    #[test]
+    fn parse_encoded_commands() {
+        for command in all_commands() {
+            let mut frame = [0; MAX_ENCODED_LENGTH];
+            let length = encode(&command, &mut frame).unwrap();
+            let frame = core::str::from_utf8(&frame[..length]).unwrap();
+
+            assert_eq!(frame.parse::<Command>().unwrap(), command);
+        }
+    }
+
+    #[test]
+    fn parse_invalid_commands() {
+        for frame in ["VOL:101;", "XYZ:1;", "POP:1;", "BTC:T;", "SYS:", ""] {
+            assert!(frame.parse::<Command>().is_err(), "{}", frame);
+        }
+    }
+
+    #[test]
+    fn encode_buffer_too_small() {
+        let mut frame = [0; 7];
+        assert!(matches!(
+            encode(&Command::SetVolume(Volume::new(100).unwrap()), &mut frame),
+            Err(Error::BufferOverflow)
+        ));
+    }
+}
@@ -13,6 +13,7 @@
 //#[cfg_attr(not(test), derive(defmt::Format))] // Only used when running on target hardware
 pub enum Error {
     NotSupportedForDeviceSource,
+    NotSupportedForProtocolVersion,
     ReadingQueryResponse,
     ParseResponse,
     NonUTF8,
@@ -27,4 +28,10 @@ pub enum Error {
     Read,
     Write,
     Unimplemented,
+    BufferOverflow,
+    InvalidTranscript,
+    Storage,
+    InvalidSettings,
+    InvalidCurve,
+    InvalidDesiredState,
 }
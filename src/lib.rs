@@ -5,8 +5,13 @@
 //! The public API is available as functions exposed by the [Up2Stream] struct.
 //!
 //! The main driver is created using `up2stream_uart::Up2Stream::new` which accepts
-//! an UART peripheral that implements the `embedded_hal::serial::{Read, Write}` traits. Tne UART
-//! connection is configured as `115200,8,N,1` with no flow control
+//! the receiving and transmitting halves of an UART peripheral that implement the
+//! `embedded_hal::serial::Read` and `embedded_hal::serial::Write` traits respectively. If the
+//! UART peripheral is not split into halves then `up2stream_uart::Up2Stream::new_combined` is used.
+//! Tne UART connection is configured as `115200,8,N,1` with no flow control
+//!
+//! Timeouts, retries, the size of the response buffer, the API version and the initialisation of the board
+//! can be configured by creating the driver with `up2stream_uart::Up2StreamBuilder`.
 //!
 //! Values are not set directly, but through the use of either enums or scalar types (such as [Volume] or [Bass])
 //! that provide range constraints on the values (for instance `Bass` can only accept values between -10 and +10 inclusive).
@@ -22,20 +27,23 @@
 //! # let initial_expectations = [
 //! # SerialTransaction::write(b';'),
 //! # SerialTransaction::write_many(b"VOL:50;"),
+//! # SerialTransaction::flush(),
 //! # SerialTransaction::write_many(b"VOL;"),
 //! # SerialTransaction::flush(),
 //! # SerialTransaction::read_many(b"VOL:50;"),
 //! # SerialTransaction::write_many(b"VOL:49;"),
+//! # SerialTransaction::flush(),
 //! # ];
 //!
-//! // Initialise a serial peripheral on your MCU that implements the traits
-//! // `embedded_hal::serial::{Read, Write}`. This is assigned
-//! // the variable `serial` in the code example
+//! // Initialise a serial peripheral on your MCU and split it into halves that implement the
+//! // traits `embedded_hal::serial::Read` and `embedded_hal::serial::Write`. These are
+//! // assigned the variables `reader` and `writer` in the code example
 //! # let mut serial = SerialMock::new(&initial_expectations);
+//! # let (reader, writer) = (serial.clone(), serial);
 //!
 //!
 //! // Initialise the drive using the previously setup serial peripheral
-//! let mut up2stream_device = Up2Stream::new(serial);
+//! let mut up2stream_device = Up2Stream::new(reader, writer);
 //!
 //! // Set the initial volume
 //! let initial_vol = Volume::new(50)?;
@@ -72,30 +80,79 @@
 //#![no_main]
 #![allow(dead_code)]
 
-use nb::block;
-
 use core::str::FromStr;
 
 use embedded_hal::serial::{Read, Write};
 
-use arrayvec::{ArrayString, ArrayVec};
+use arrayvec::ArrayString;
 
 // TODO consider this for error type conversion: https://doc.rust-lang.org/std/convert/trait.From.html
+mod builder;
+mod cache;
+mod clock;
+mod command;
 mod error;
+mod loudness;
+mod macros;
 mod parameter_types;
-
+mod pipeline;
+mod ramp;
+mod raw;
+mod reconcile;
+mod relative;
+mod scene;
+mod serial;
+mod settings;
+mod source_volume;
+mod switch;
+
+#[cfg(any(test, feature = "sim"))]
+pub mod sim;
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod transcript;
+
+pub use crate::builder::{Config, ProtocolVersion, Up2StreamBuilder, DEFAULT_RETRIES};
+pub use crate::cache::{Cached, NotificationError};
+pub use crate::clock::Clock;
+pub use crate::command::{encode, Command, MAX_ENCODED_LENGTH};
+use crate::command::{encode_frame, MAX_PARAMETER_LENGTH};
 pub use crate::error::Error;
+pub use crate::loudness::{VolumeCurve, TONE_STEP_DB};
+use crate::pipeline::PartialFrame;
+
+pub use crate::pipeline::{Query, Reply, DEFAULT_PIPELINE_TIMEOUT_POLLS};
+pub use crate::ramp::VolumeRamp;
+pub use crate::raw::Response;
+pub use crate::reconcile::{
+    Correction, Corrections, DesiredState, ReconcileError, Reconciler, MAX_CORRECTIONS,
+};
+pub use crate::scene::Scene;
+pub use crate::serial::{Blocking, Combined, Transmit};
+pub use crate::settings::{SettingsStore, ENCODED_SETTINGS_LENGTH, SETTINGS_VERSION};
+pub use crate::source_volume::{
+    SourceVolumes, VolumeMemory, ENCODED_SOURCE_VOLUMES_LENGTH, SOURCE_VOLUMES_VERSION,
+};
+pub use crate::switch::SwitchPauses;
 
 /// Re-exports of parameter types
 pub use crate::parameter_types::{
-    AudioChannel, Bass, DeviceStatus, Led, LoopMode, MultiroomState, PlayPreset, Playback,
-    ScalarParameter, Source, Switch, SystemControl, Treble, Volume,
+    AudioChannel, Bass, CheckedValue, DeviceStatus, Led, LoopMode, MultiroomState, PlayPreset,
+    Playback, ScalarParameter, ScalarRange, Source, Switch, SystemControl, Treble, Volume,
 };
 
+/// `#[derive(ScalarParameter)]` for new ranged parameter types, see [ScalarParameter]
+#[cfg(feature = "derive")]
+pub use up2stream_uart_derive::ScalarParameter;
+
 // #[cfg(doctest)]
 // use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
 
-const MAX_SIZE_RESPONSE: usize = 1024;
+/// The default size in bytes of the buffer holding a query response.
+///
+/// A different size can be chosen with the const generic parameter of [Up2Stream], see [Up2Stream::with_buffer].
+pub const DEFAULT_RESPONSE_SIZE: usize = 1024;
 
 // Commands
 const COMMAND_VER: &str = "VER";
@@ -130,29 +187,183 @@ const TERMINATOR: u8 = b';';
 const PARAMETER_START: u8 = b':';
 const PARAMETER_DELIMITER: u8 = b',';
 
+// The characters accepted in the parameters of a response
+#[derive(Clone, Copy)]
+enum Parameters {
+    // Only the characters of the responses the driver knows, anything else is ill-formed
+    Strict,
+    // Any printable ASCII character or space except the terminator, for raw queries
+    Printable,
+}
+
+// How long the reader has been without data, for the timeouts of reads
+#[derive(Default)]
+struct Idle {
+    polls: u32,
+    since_ms: Option<u32>,
+}
+
 /// The UART driver for the **Arylic Up2Stream Pro** board.
-//pub struct Up2Stream<'a, UART: Read<u8> + Write<u8>> {
-pub struct Up2Stream<UART: Read<u8> + Write<u8>> {
-    uart: UART,
+///
+/// The driver uses a reader `R` for receiving data from the board and a writer `W` for
+/// transmitting data to it.
+///
+/// The const generic parameter `N` sets the size in bytes of the buffer holding query
+/// responses. It defaults to [DEFAULT_RESPONSE_SIZE]. A response that does not fit into
+/// the buffer results in an [Error::BufferOverflow].
+///
+/// To change the default settings use [Up2StreamBuilder].
+pub struct Up2Stream<R: Read<u8>, W: Transmit<R>, const N: usize = DEFAULT_RESPONSE_SIZE> {
+    reader: R,
+    writer: W,
+
+    config: Config,
+
+    // Buffer holding the parameters of the last query response. It is owned by the
+    // driver and reused by every query.
+    response: ArrayString<N>,
+
+    // Clock measuring Config::timeout_ms, see Up2StreamBuilder::timeout_ms
+    clock: Option<fn() -> u32>,
+
+    // Frame left incomplete by the last read that did not wait for data, see Cached::poll_notifications
+    partial_frame: PartialFrame,
+}
+
+impl<R, W> Up2Stream<R, W>
+where
+    R: Read<u8>,
+    W: Transmit<R>,
+{
+    /// Create a new Up2Stream driver from the receiving half of an UART that implements the `Read` trait
+    /// and the transmitting half that implements the `Write` trait.
+    ///
+    /// The driver uses a response buffer of [DEFAULT_RESPONSE_SIZE] bytes.
+    pub fn new(reader: R, writer: W) -> Up2Stream<R, W> {
+        Self::with_buffer(reader, writer)
+    }
+}
 
-    response: ArrayString<MAX_SIZE_RESPONSE>,
+impl<UART> Up2Stream<UART, Combined>
+where
+    UART: Read<u8> + Write<u8>,
+{
+    /// Create a new Up2Stream driver from an UART object that implements both the `Read` and `Write` traits.
+    ///
+    /// The driver uses a response buffer of [DEFAULT_RESPONSE_SIZE] bytes.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use up2stream_uart::Up2Stream;
+    /// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+    /// # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
+    /// let mut up2stream_driver = Up2Stream::new_combined(uart);
+    ///
+    /// // Get the UART back again
+    /// let (uart, _) = up2stream_driver.release();
+    /// ```
+    pub fn new_combined(uart: UART) -> Up2Stream<UART, Combined> {
+        Self::with_buffer(uart, Combined)
+    }
 }
 
 //impl<'a, UART> Up2Stream<'a, UART>
-impl<UART> Up2Stream<UART>
+impl<R, W, const N: usize> Up2Stream<R, W, N>
 where
-    UART: Write<u8> + Read<u8>,
+    R: Read<u8>,
+    W: Transmit<R>,
 {
-    /// Create a new Up2Stream driver from an UART object that implements the `Read` and `Write` traits.
-    pub fn new(mut uart: UART) -> Up2Stream<UART> {
-        // This seems to be required by the device before usage.
+    /// Create a new Up2Stream driver with a response buffer of `N` bytes.
+    ///
+    /// If the UART peripheral is not split, then [Combined] is used as the writer.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use up2stream_uart::{Up2Stream, Combined};
+    /// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+    /// # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
+    /// // Only use 64 bytes for responses
+    /// let mut up2stream_driver: Up2Stream<_, _, 64> = Up2Stream::with_buffer(uart, Combined);
+    /// ```
+    pub fn with_buffer(reader: R, writer: W) -> Up2Stream<R, W, N> {
+        let mut driver = Self::from_config(reader, writer, Config::default());
+
         // It can fail, but the uart channel is then usable
-        block!(uart.write(TERMINATOR)).ok();
+        driver.wake_up().ok();
 
+        driver
+    }
+
+    // Create the driver without any communication with the board.
+    pub(crate) fn from_config(reader: R, writer: W, config: Config) -> Up2Stream<R, W, N> {
         Up2Stream {
-            uart,
-            response: ArrayString::<MAX_SIZE_RESPONSE>::new(),
+            reader,
+            writer,
+            config,
+            response: ArrayString::<N>::new(),
+            partial_frame: PartialFrame::default(),
+            clock: None,
+        }
+    }
+
+    pub(crate) fn set_clock(&mut self, clock: fn() -> u32) {
+        self.clock = Some(clock);
+    }
+
+    // Count a read without data and return if the reader has been idle for longer than the
+    // timeout. This is Config::timeout_ms if the driver has a clock, otherwise `timeout_polls`.
+    fn timed_out(&mut self, idle: &mut Idle, timeout_polls: Option<u32>) -> bool {
+        match (self.clock.as_mut(), self.config.timeout_ms) {
+            (Some(clock), Some(timeout_ms)) => {
+                let now_ms = clock.now_ms();
+                let since_ms = *idle.since_ms.get_or_insert(now_ms);
+                now_ms.wrapping_sub(since_ms) > timeout_ms
+            }
+            _ => {
+                idle.polls += 1;
+                matches!(timeout_polls, Some(timeout) if idle.polls > timeout)
+            }
+        }
+    }
+
+    // This seems to be required by the device before usage.
+    pub(crate) fn wake_up(&mut self) -> Result<(), Error> {
+        self.writer.write_all(&mut self.reader, &[TERMINATOR])
+    }
+
+    /// Get the settings used by the driver.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Release the driver, returning the reader and writer it was created with.
+    pub fn release(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+
+    /// Send a [Command] to the board.
+    ///
+    /// The methods changing the settings of the board are built on this. It can be used directly
+    /// to send commands that have been stored or queued as data. A command needing a newer
+    /// [ProtocolVersion] than the configured one is not sent and returns
+    /// [Error::NotSupportedForProtocolVersion].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use up2stream_uart::{Up2Stream, Command, Source};
+    /// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+    /// # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
+    /// # let mut up2stream_driver =Up2Stream::new_combined(uart);
+    /// up2stream_driver.send(&Command::SelectSource(Source::Usb)).unwrap();
+    /// ```
+    pub fn send(&mut self, command: &Command) -> Result<(), Error> {
+        if command.protocol_version() > self.config.protocol_version {
+            return Err(Error::NotSupportedForProtocolVersion);
         }
+
+        let mut parameter = [0; MAX_PARAMETER_LENGTH];
+
+        self.send_command(command.name(), command.parameter(&mut parameter))
     }
 
     /// Get the device firmware version as a string in the form
@@ -162,19 +373,12 @@ where
     /// use up2stream_uart::Up2Stream;
     /// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
     /// # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
-    /// # let mut up2stream_driver =Up2Stream::new(uart);
+    /// # let mut up2stream_driver =Up2Stream::new_combined(uart);
     /// let firmware : &str = up2stream_driver.firmware_version().unwrap();
     ///
     /// ```
     pub fn firmware_version(&mut self) -> Result<&str, Error> {
-        self.response = self
-            //.send_query(COMMAND_VER)
-            //.map_err(|_| Error::SendCommand)?;
-            .send_query(COMMAND_VER)?;
-
-        let s = self.response.as_str();
-
-        Ok(s)
+        self.send_query(COMMAND_VER)
     }
 
     /// Get the device status as a [DeviceStatus] struct.
@@ -185,7 +389,7 @@ where
     /// use up2stream_uart::Up2Stream;
     /// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
     /// # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
-    /// # let mut up2stream_driver =Up2Stream::new(uart);
+    /// # let mut up2stream_driver =Up2Stream::new_combined(uart);
     /// let device_status = up2stream_driver.status().unwrap();
     /// let source = device_status.source;
     ///
@@ -196,23 +400,7 @@ where
             .send_query(COMMAND_STATUS)
             .map_err(|_| Error::SendCommand)?;
 
-        //let status_fields: ArrayVec<&str, 20> = response.split(&[':', ',']).collect();
-        let status_fields: ArrayVec<&str, 20> = response.split(&[',']).collect();
-
-        let device_status = DeviceStatus {
-            source: Source::from_str(status_fields[0])?,
-            mute: Switch::from_str(status_fields[1])?.to_bool()?,
-            volume: Volume::from_str(status_fields[2])?,
-            treble: Treble::from_str(status_fields[3])?,
-            bass: Bass::from_str(status_fields[4])?,
-            net: Switch::from_str(status_fields[5])?.to_bool()?,
-            internet: Switch::from_str(status_fields[6])?.to_bool()?,
-            playing: Switch::from_str(status_fields[7])?.to_bool()?,
-            led: Switch::from_str(status_fields[8])?.to_bool()?,
-            upgrading: Switch::from_str(status_fields[9])?.to_bool()?,
-        };
-
-        Ok(device_status)
+        DeviceStatus::from_str(response)
     }
 
     /// Reset, reboot or put into standby the device.
@@ -222,16 +410,12 @@ where
     /// use up2stream_uart::{Up2Stream, SystemControl};
     /// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
     /// # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
-    /// # let mut up2stream_driver =Up2Stream::new(uart);
+    /// # let mut up2stream_driver =Up2Stream::new_combined(uart);
     /// let device_status = up2stream_driver.execute_system_control(SystemControl::Reboot).unwrap();
     ///
     /// ```
     pub fn execute_system_control(&mut self, control: SystemControl) -> Result<(), Error> {
-        let mut buf = [0; 64];
-
-        self.send_command(COMMAND_SYSTEM_CONTROL, control.to_parameter_str(&mut buf))?;
-
-        Ok(())
+        self.send(&Command::SystemControl(control))
     }
 
     /// Get the status of the internet connection
@@ -242,7 +426,7 @@ where
             return Err(Error::IllFormedReponse);
         }
 
-        let internet_connection_status = Switch::from_str(response.as_str())?;
+        let internet_connection_status = Switch::from_str(response)?;
 
         internet_connection_status.to_bool()
     }
@@ -255,7 +439,7 @@ where
             return Err(Error::IllFormedReponse);
         }
 
-        Switch::from_str(response.as_str())?.to_bool()
+        Switch::from_str(response)?.to_bool()
     }
 
     /// Enable or disable audio output. For instance:
@@ -264,17 +448,13 @@ where
     /// use up2stream_uart::Up2Stream;
     /// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
     /// # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
-    /// # let mut up2stream_driver =Up2Stream::new(uart);
+    /// # let mut up2stream_driver =Up2Stream::new_combined(uart);
     /// // Enable audio output
     /// up2stream_driver.set_audio_out(true).unwrap();
     ///
     /// ```
     pub fn set_audio_out(&mut self, enable: bool) -> Result<(), Error> {
-        let switch = Switch::from(enable);
-
-        let mut buf = [0; 1];
-
-        self.send_command(COMMAND_AUD, switch.to_parameter_str(&mut buf))
+        self.send(&Command::SetAudioOut(enable))
     }
 
     /// Get the current input source.
@@ -284,7 +464,7 @@ where
     /// use up2stream_uart::{Up2Stream, Source};
     /// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
     /// # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
-    /// # let mut up2stream_driver =Up2Stream::new(uart);
+    /// # let mut up2stream_driver =Up2Stream::new_combined(uart);
     ///
     /// let source: Source = up2stream_driver.input_source().unwrap();
     /// match source {
@@ -295,7 +475,7 @@ where
     pub fn input_source(&mut self) -> Result<Source, Error> {
         let response = self.send_query(COMMAND_SRC)?;
 
-        let source = Source::from_str(response.as_str())?;
+        let source = Source::from_str(response)?;
         Ok(source)
     }
 
@@ -307,13 +487,12 @@ where
     /// use up2stream_uart::{Up2Stream, Source};
     /// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
     /// # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
-    /// # let mut up2stream_driver =Up2Stream::new(uart);
+    /// # let mut up2stream_driver =Up2Stream::new_combined(uart);
     ///
     /// up2stream_driver.select_input_source(Source::Bluetooth).unwrap();
     /// ```
     pub fn select_input_source(&mut self, source: Source) -> Result<(), Error> {
-        let mut buf = [0; 20];
-        self.send_command(COMMAND_SRC, source.to_parameter_str(&mut buf))
+        self.send(&Command::SelectSource(source))
     }
 
     /// Get the current volume
@@ -323,7 +502,7 @@ where
     ///  use up2stream_uart::{Up2Stream, Volume, ScalarParameter};
     ///  # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
     ///  # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
-    ///  # let mut up2stream_driver =Up2Stream::new(uart);
+    ///  # let mut up2stream_driver =Up2Stream::new_combined(uart);
     /// let volume = up2stream_driver.volume().unwrap();
     /// // Now get the value of the volume
     /// let volume_value: i8 = volume.get();
@@ -332,7 +511,7 @@ where
     pub fn volume(&mut self) -> Result<Volume, Error> {
         let response = self.send_query(COMMAND_VOL)?;
 
-        let volume = Volume::from_str(response.as_str())?;
+        let volume = Volume::from_str(response)?;
 
         Ok(volume)
     }
@@ -346,20 +525,19 @@ where
     /// use up2stream_uart::{Up2Stream, Volume, ScalarParameter};
     ///  # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
     ///  # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
-    ///  # let mut up2stream_driver =Up2Stream::new(uart);
+    ///  # let mut up2stream_driver =Up2Stream::new_combined(uart);
     /// let volume = Volume::new(52).unwrap();
     /// up2stream_driver.set_volume(volume).unwrap();
     /// ```
     pub fn set_volume(&mut self, volume: Volume) -> Result<(), Error> {
-        let mut buf = [0; 3];
-        self.send_command(COMMAND_VOL, volume.to_parameter_str(&mut buf))
+        self.send(&Command::SetVolume(volume))
     }
 
     /// Get if the audio is muted or not.
     pub fn mute_status(&mut self) -> Result<bool, Error> {
         let response = self.send_query(COMMAND_MUT)?;
 
-        let mute_status = Switch::from_str(response.as_str())?;
+        let mute_status = Switch::from_str(response)?;
 
         mute_status.to_bool()
     }
@@ -373,7 +551,7 @@ where
     /// use up2stream_uart::{Up2Stream, Switch};
     ///  # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
     ///  # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
-    ///  # let mut up2stream_driver =Up2Stream::new(uart);
+    ///  # let mut up2stream_driver =Up2Stream::new_combined(uart);
     /// up2stream_driver.set_mute(Switch::On).unwrap();
     /// ```
     /// To toggle the mute status:
@@ -381,12 +559,11 @@ where
     /// use up2stream_uart::{Up2Stream, Switch};
     ///  # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
     ///  # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
-    ///  # let mut up2stream_driver =Up2Stream::new(uart);
+    ///  # let mut up2stream_driver =Up2Stream::new_combined(uart);
     /// up2stream_driver.set_mute(Switch::Toggle).unwrap();
     /// ```
     pub fn set_mute(&mut self, switch: Switch) -> Result<(), Error> {
-        let mut buf = [0; 1];
-        self.send_command(COMMAND_MUT, switch.to_parameter_str(&mut buf))
+        self.send(&Command::SetMute(switch))
     }
 
     /// Get the bass value, e.g.;
@@ -394,14 +571,14 @@ where
     /// use up2stream_uart::{Up2Stream, Bass};
     ///  # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
     ///  # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
-    ///  # let mut up2stream_driver =Up2Stream::new(uart);
+    ///  # let mut up2stream_driver =Up2Stream::new_combined(uart);
     /// let bass: Bass = up2stream_driver.bass().unwrap();
     ///
     /// ```
     pub fn bass(&mut self) -> Result<Bass, Error> {
         let response = self.send_query(COMMAND_BAS)?;
 
-        let bass = Bass::from_str(response.as_str())?;
+        let bass = Bass::from_str(response)?;
 
         Ok(bass)
     }
@@ -413,14 +590,13 @@ where
     /// use up2stream_uart::{Up2Stream, Bass};
     ///  # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
     ///  # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
-    ///  # let mut up2stream_driver =Up2Stream::new(uart);
+    ///  # let mut up2stream_driver =Up2Stream::new_combined(uart);
     /// let bass = Bass::new(-6).unwrap();
     /// up2stream_driver.set_bass(bass).unwrap();
     ///
     /// ```
     pub fn set_bass(&mut self, bass: Bass) -> Result<(), Error> {
-        let mut buf = [0; 3];
-        self.send_command(COMMAND_BAS, bass.to_parameter_str(&mut buf))
+        self.send(&Command::SetBass(bass))
     }
 
     /// Get the treble value.
@@ -430,7 +606,7 @@ where
     /// use up2stream_uart::{Up2Stream, Treble, ScalarParameter};
     ///  # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
     ///  # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
-    ///  # let mut up2stream_driver =Up2Stream::new(uart);
+    ///  # let mut up2stream_driver =Up2Stream::new_combined(uart);
     ///
     /// let treble = up2stream_driver.treble().unwrap();
     /// let treble_value : i8 = treble.get();
@@ -439,7 +615,7 @@ where
     pub fn treble(&mut self) -> Result<Treble, Error> {
         let response = self.send_query(COMMAND_TRE)?;
 
-        let treble = Treble::from_str(response.as_str())?;
+        let treble = Treble::from_str(response)?;
 
         Ok(treble)
     }
@@ -451,14 +627,13 @@ where
     /// use up2stream_uart::{Up2Stream, Treble};
     ///  # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
     ///  # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
-    ///  # let mut up2stream_driver =Up2Stream::new(uart);
+    ///  # let mut up2stream_driver =Up2Stream::new_combined(uart);
     /// let treble = Treble::new(-6).unwrap();
     /// up2stream_driver.set_treble(treble).unwrap();
     ///
     /// ```
     pub fn set_treble(&mut self, treble: Treble) -> Result<(), Error> {
-        let mut buf = [0; 3];
-        self.send_command(COMMAND_TRE, treble.to_parameter_str(&mut buf))
+        self.send(&Command::SetTreble(treble))
     }
 
     /// Toggle between play and pause.
@@ -468,13 +643,13 @@ where
     ///  # use up2stream_uart::{Up2Stream};
     ///  # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
     ///  # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
-    ///  # let mut up2stream_driver =Up2Stream::new(uart);
+    ///  # let mut up2stream_driver =Up2Stream::new_combined(uart);
     ///
     ///  up2stream_driver.play_pause_toggle().unwrap();
     ///
     /// ```
     pub fn play_pause_toggle(&mut self) -> Result<(), Error> {
-        self.send_command(COMMAND_POP, b"")
+        self.send(&Command::PlayPause)
     }
 
     /// Stop playing.
@@ -486,7 +661,7 @@ where
         let source = self.input_source()?;
 
         match source {
-            Source::Net | Source::Usb => self.send_command(COMMAND_STP, b""),
+            Source::Net | Source::Usb => self.send(&Command::Stop),
             _ => Err(Error::NotSupportedForDeviceSource),
         }
     }
@@ -500,7 +675,7 @@ where
         let source = self.input_source()?;
 
         match source {
-            Source::Bluetooth | Source::Net | Source::Usb => self.send_command(COMMAND_NXT, b""),
+            Source::Bluetooth | Source::Net | Source::Usb => self.send(&Command::NextTrack),
             _ => Err(Error::NotSupportedForDeviceSource),
         }
     }
@@ -516,7 +691,7 @@ where
         let source = self.input_source()?;
 
         match source {
-            Source::Bluetooth | Source::Net | Source::Usb => self.send_command(COMMAND_PRE, b""),
+            Source::Bluetooth | Source::Net | Source::Usb => self.send(&Command::PreviousTrack),
             _ => Err(Error::NotSupportedForDeviceSource),
         }
     }
@@ -535,7 +710,7 @@ where
 
         let response = self.send_query(COMMAND_BTC)?;
 
-        let status = Switch::from_str(response.as_str())?;
+        let status = Switch::from_str(response)?;
 
         status.to_bool()
     }
@@ -552,10 +727,7 @@ where
             return Err(Error::NotSupportedForDeviceSource);
         };
 
-        let reconnect = Switch::On;
-
-        let mut buf = [0; 1];
-        self.send_command(COMMAND_BTC, reconnect.to_parameter_str(&mut buf))
+        self.send(&Command::ConnectBluetooth)
     }
 
     /// Disconnect the current bluetooth device
@@ -570,20 +742,27 @@ where
             return Err(Error::NotSupportedForDeviceSource);
         };
 
-        let disconnect = Switch::Off;
-
-        let mut buf = [0; 1];
-        self.send_command(COMMAND_BTC, disconnect.to_parameter_str(&mut buf))
+        self.send(&Command::DisconnectBluetooth)
     }
 
     #[doc(hidden)]
     pub fn playback_status(&mut self) -> Result<Playback, Error> {
         todo!()
     }
-    #[doc(hidden)]
+
+    /// Get the audio channel that is output.
     pub fn audio_channel(&mut self) -> Result<AudioChannel, Error> {
-        todo!()
+        let response = self.send_query(COMMAND_CHN)?;
+
+        AudioChannel::from_str(response)
     }
+
+    /// Select the audio channel that is output, e.g. [AudioChannel::Left] when two boards drive the
+    /// speakers of a stereo pair.
+    pub fn set_audio_channel(&mut self, channel: AudioChannel) -> Result<(), Error> {
+        self.send(&Command::SetAudioChannel(channel))
+    }
+
     #[doc(hidden)]
     pub fn multiroom_state(&mut self) -> Result<MultiroomState, Error> {
         todo!()
@@ -592,59 +771,76 @@ where
     pub fn set_multiroom_state(&mut self, _state: MultiroomState) -> Result<(), Error> {
         todo!();
     }
-    #[doc(hidden)]
+
+    /// Get if the LEDs are switched on ([Led::On]) or off ([Led::Off]).
     pub fn led(&mut self) -> Result<Led, Error> {
-        todo!()
+        let response = self.send_query(COMMAND_LED)?;
+
+        match Led::from_str(response)? {
+            Led::Toogle => Err(Error::IllFormedReponse),
+            led => Ok(led),
+        }
     }
-    #[doc(hidden)]
-    pub fn set_led(&mut self, _led_status: Led) -> Result<(), Error> {
-        todo!();
+
+    /// Switch the LEDs on, off or toggle them.
+    pub fn set_led(&mut self, led_status: Led) -> Result<(), Error> {
+        self.send(&Command::SetLed(led_status))
     }
-    #[doc(hidden)]
+
+    /// Get if the beep sound is enabled.
     pub fn beep(&mut self) -> Result<bool, Error> {
-        todo!();
+        let response = self.send_query(COMMAND_BEP)?;
+
+        Switch::from_str(response)?.to_bool()
     }
 
-    #[doc(hidden)]
-    pub fn set_beep(&mut self, _beep: bool) -> Result<(), Error> {
-        todo!()
+    /// Enable or disable the beep sound.
+    pub fn set_beep(&mut self, beep: bool) -> Result<(), Error> {
+        self.send(&Command::SetBeep(beep))
     }
 
     #[doc(hidden)]
     pub fn set_play_preset(&mut self, _preset: PlayPreset) -> Result<(), Error> {
         todo!();
     }
-    #[doc(hidden)]
+    /// Get if virtual bass is enabled.
     pub fn virtual_bass(&mut self) -> Result<bool, Error> {
-        todo!();
+        let response = self.send_query(COMMAND_VBS)?;
+
+        Switch::from_str(response)?.to_bool()
     }
 
-    #[doc(hidden)]
+    /// Enable virtual bass.
     pub fn enable_virtual_bass(&mut self) -> Result<(), Error> {
-        todo!();
+        self.send(&Command::SetVirtualBass(Switch::On))
     }
 
-    #[doc(hidden)]
+    /// Disable virtual bass.
     pub fn disable_virtual_bass(&mut self) -> Result<(), Error> {
-        todo!();
+        self.send(&Command::SetVirtualBass(Switch::Off))
     }
 
-    #[doc(hidden)]
+    /// Toggle virtual bass.
     pub fn toggle_virtual_bass(&mut self) -> Result<(), Error> {
-        todo!();
+        self.send(&Command::SetVirtualBass(Switch::Toggle))
     }
     #[doc(hidden)]
     pub fn reset_wifi(&mut self) -> Result<(), Error> {
         todo!();
     }
-    #[doc(hidden)]
+
+    /// Get the loop mode for network playback.
     pub fn loop_mode(&mut self) -> Result<LoopMode, Error> {
-        todo!();
+        let response = self.send_query(COMMAND_LPM)?;
+
+        LoopMode::from_str(response)
     }
-    #[doc(hidden)]
-    pub fn set_loop_mode(&mut self, _loop_mode: LoopMode) -> Result<(), Error> {
-        todo!();
+
+    /// Set the loop mode for network playback.
+    pub fn set_loop_mode(&mut self, loop_mode: LoopMode) -> Result<(), Error> {
+        self.send(&Command::SetLoopMode(loop_mode))
     }
+
     #[doc(hidden)]
     pub fn device_name(&mut self) -> Result<&str, Error> {
         todo!();
@@ -666,34 +862,23 @@ where
 
     //    ******* TODO more commands for version 4 here https://docs.google.com/spreadsheets/d/1LT6nsaCmg2B6vV0M2iOusxZ-hIqgDeqB0SLPTtZokCo/edit#gid=1444188925
 
-    // Send a command with any specified parameters.
-    // Commands are send as bytes with the following syntax (BNF)
-    //
-    //    <command> = <command_name> ";" | <command_name> ":" <parameter> <terminator>
-    //    <command> ::= <alphanumeric> | <command>
-    //    <terminator> ::= ";"
+    // Send a command with any specified parameters, or a query if `parameter` is empty.
+    // The frame is encoded into a buffer on the stack with the syntax described for
+    // command::encode_frame and transmitted with send_frame.
     fn send_command(&mut self, command: &str, parameter: &[u8]) -> Result<(), Error> {
-        // Now send the command characters
-        for c in command.chars() {
-            self.uart.write(c as u8).map_err(|_| Error::SendCommand)?;
-        }
-
-        // Send parameters if available
-        if !parameter.is_empty() {
-            self.uart
-                .write(PARAMETER_START)
-                .map_err(|_| Error::SendCommand)?;
-            for c in parameter {
-                self.uart.write(*c).map_err(|_| Error::SendCommand)?;
-            }
-        }
+        let mut frame = [0; MAX_ENCODED_LENGTH];
+        let length = encode_frame(command.as_bytes(), [parameter], &mut frame)?;
 
-        // Send termination character
-        self.uart
-            .write(TERMINATOR)
-            .map_err(|_| Error::SendCommand)?;
+        self.send_frame(&frame[..length])
+            .map_err(|_| Error::SendCommand)
+    }
 
-        Ok(())
+    // Transmit an encoded frame, i.e. a command or query. The frame is written in bulk and
+    // flushed, so that both commands and queries wait in the same way if the transmitter is not
+    // ready, and a Blocking writer sends it with a single bwrite_all.
+    fn send_frame(&mut self, frame: &[u8]) -> Result<(), Error> {
+        self.writer.write_all(&mut self.reader, frame)?;
+        self.writer.flush(&mut self.reader)
     }
 
     // Send a query and read the response. Queries are sent with the following syntax (BNF):
@@ -715,19 +900,38 @@ where
     //  <control_char> ::=   "\n" | "\r"
     //  <character> is any printable character
     //
-    fn send_query(&mut self, command: &str) -> Result<ArrayString<MAX_SIZE_RESPONSE>, Error> {
-        const MAX_NUMBER_RESENDS: u8 = 3;
-
-        let mut query_response = ArrayString::<MAX_SIZE_RESPONSE>::new();
+    // The response parameters are collected in the driver owned response buffer, which is reused
+    // for every query, so that the stack usage does not depend on the size of the buffer. The returned
+    // string slice borrows from this buffer and is only valid until the next query.
+    //
+    // If no valid response is received, then the query is resent up to the number of retries
+    // set in the configuration.
+    fn send_query(&mut self, command: &str) -> Result<&str, Error> {
+        self.send_query_with(command, Parameters::Strict)
+    }
 
-        // Send  the command characters
-        for c in command.chars() {
-            block!(self.uart.write(c as u8)).map_err(|_| Error::SendCommand)?;
+    // Send a query as send_query, reading the parameters of the response as given.
+    fn send_query_with(&mut self, command: &str, parameters: Parameters) -> Result<&str, Error> {
+        let mut attempt = 0;
+        loop {
+            match self.query(command, parameters) {
+                Ok(()) => break,
+                Err(
+                    Error::Timeout | Error::Read | Error::ParseResponse | Error::IllFormedReponse,
+                ) if attempt < self.config.retries => attempt += 1,
+                Err(e) => return Err(e),
+            }
         }
 
-        block!(self.uart.write(TERMINATOR)).map_err(|_| Error::SendCommand)?;
+        Ok(self.response.as_str())
+    }
+
+    // Send a query once and read the response into the response buffer.
+    fn query(&mut self, command: &str, parameters: Parameters) -> Result<(), Error> {
+        self.response.clear();
+        self.partial_frame = PartialFrame::default();
 
-        block!(self.uart.flush()).map_err(|_| Error::SendCommand)?;
+        self.send_command(command, b"")?;
 
         //#[cfg_attr(not(test), derive(defmt::Format))] // Only used when running on target hardware
         enum Symbol {
@@ -737,6 +941,7 @@ where
             Terminator(u8),
             ParameterStart(u8),
             ParameterDelimiter(u8),
+            Other(u8),
         }
 
         impl Symbol {
@@ -747,6 +952,7 @@ where
                     Self::ParameterStart(c) => *c as char,
                     Self::ParameterDelimiter(c) => *c as char,
                     Self::Terminator(c) => *c as char,
+                    Self::Other(c) => *c as char,
                     Self::Block => '|',
                 }
             }
@@ -762,25 +968,45 @@ where
 
         let mut state = ParseState::Command;
         let mut command_string_index = 0;
+        let mut idle = Idle::default();
 
         // Read and parse the response
         loop {
-            let symbol = match self.uart.read() {
+            let symbol = match self.reader.read() {
+                // Raw responses can contain e.g. names with spaces and punctuation
+                Ok(c)
+                    if matches!(
+                        (parameters, state),
+                        (Parameters::Printable, ParseState::Parameter)
+                    ) && (c.is_ascii_graphic() || c == b' ')
+                        && c != TERMINATOR =>
+                {
+                    Ok(Symbol::Character(c))
+                }
                 Ok(c) if c.is_ascii_alphanumeric() => Ok(Symbol::Character(c)),
                 Ok(c) if c == b'-' => Ok(Symbol::Character(c)), // Occurs in the version number and negative numbers
                 Ok(c) if c == b'+' => Ok(Symbol::Character(c)), // Occurs in certain commands
+                Ok(c) if c == b'.' => Ok(Symbol::Character(c)), // Occurs in the version number
                 Ok(c) if c.is_ascii_control() => Ok(Symbol::ControlCharacter(c)),
                 Ok(c) if c == TERMINATOR => Ok(Symbol::Terminator(c)),
                 Ok(c) if c == PARAMETER_START => Ok(Symbol::ParameterStart(c)),
                 Ok(c) if c == PARAMETER_DELIMITER => Ok(Symbol::ParameterDelimiter(c)),
-                // Other characters should not occur
-                Ok(_) => Err(Error::Read),
+                // Other characters should not occur in a response, but can be line noise
+                Ok(c) => Ok(Symbol::Other(c)),
                 // Assuming that Err(WouldBlock) is an end of record.
                 Err(nb::Error::WouldBlock) => Ok(Symbol::Block),
                 // Read error condition
                 Err(nb::Error::Other(_e)) => return Err(Error::Read),
             }?;
 
+            if let Symbol::Block = symbol {
+                if self.timed_out(&mut idle, self.config.timeout_polls) {
+                    return Err(Error::Timeout);
+                }
+            } else {
+                idle = Idle::default();
+            }
+
             match (state, symbol) {
                 (ParseState::Command, Symbol::Character(c)) => {
                     if c == command.as_bytes()[command_string_index] {
@@ -790,6 +1016,11 @@ where
                         } else {
                             state = ParseState::ValidatedCommand;
                         };
+                    } else if c == command.as_bytes()[0] {
+                        // Resynchronise on what could be the start of the response
+                        command_string_index = 1;
+                    } else {
+                        command_string_index = 0;
                     };
                 }
                 (ParseState::Command, Symbol::Block) => state = ParseState::Command,
@@ -804,12 +1035,16 @@ where
                     state = ParseState::ValidatedCommand
                 }
                 (ParseState::ValidatedCommand, _) => return Err(Error::ParseResponse),
-                (ParseState::Parameter, Symbol::Character(c)) => query_response.push(c as char),
+                (ParseState::Parameter, Symbol::Character(c)) => self
+                    .response
+                    .try_push(c as char)
+                    .map_err(|_| Error::BufferOverflow)?,
 
                 // Currently not seperating parameters and just treating them all as a string.
-                (ParseState::Parameter, Symbol::ParameterDelimiter(_)) => {
-                    query_response.push(PARAMETER_DELIMITER as char)
-                }
+                (ParseState::Parameter, Symbol::ParameterDelimiter(_)) => self
+                    .response
+                    .try_push(PARAMETER_DELIMITER as char)
+                    .map_err(|_| Error::BufferOverflow)?,
 
                 (ParseState::Parameter, Symbol::Terminator(_)) => break, // Finished parsing
                 (ParseState::Parameter, Symbol::Block) => state = ParseState::Parameter,
@@ -818,7 +1053,7 @@ where
             }
         }
 
-        Ok(query_response)
+        Ok(())
     }
 }
 
@@ -0,0 +1,319 @@
+//! Conversions between the parameter scales of the board and decibels, see [VolumeCurve].
+//!
+//! The UART API does not document how the parameters map to gain, so the conversions are
+//! approximations. No `libm` is needed: the volume curve is a table interpolated linearly in dB.
+
+use crate::error::Error;
+use crate::{Bass, ScalarParameter, Treble, Volume};
+
+/// The assumed change of the bass or treble filter in dB per step of [Bass] or [Treble].
+pub const TONE_STEP_DB: f32 = 1.0;
+
+// 20 * log10(volume / 100), the gain if the volume is linear in amplitude
+const LINEAR_AMPLITUDE: [(Volume, f32); 18] = [
+    (Volume(1), -40.0),
+    (Volume(2), -33.98),
+    (Volume(3), -30.46),
+    (Volume(4), -27.96),
+    (Volume(5), -26.02),
+    (Volume(6), -24.44),
+    (Volume(8), -21.94),
+    (Volume(10), -20.0),
+    (Volume(13), -17.72),
+    (Volume(16), -15.92),
+    (Volume(20), -13.98),
+    (Volume(25), -12.04),
+    (Volume(32), -9.9),
+    (Volume(40), -7.96),
+    (Volume(50), -6.02),
+    (Volume(63), -4.01),
+    (Volume(80), -1.94),
+    (Volume(100), 0.0),
+];
+
+/// Maps a [Volume] to an approximate gain in dB with a taper table.
+///
+/// Volume 0 mutes the output and maps to `f32::NEG_INFINITY`. Between the points of the table the
+/// gain is interpolated linearly, below the first point the gain of the first point is used.
+///
+/// As the 0..100 scale is not linear in loudness, stepping the volume by a fixed number of dB with
+/// [VolumeCurve::step] or mapping an encoder position with [VolumeCurve::from_position] gives
+/// changes that sound even across the whole range.
+///
+/// # Example
+/// ```
+/// use up2stream_uart::{volume, VolumeCurve};
+///
+/// let curve = VolumeCurve::default();
+///
+/// assert_eq!(curve.to_db(volume!(50)), -6.02);
+/// assert_eq!(curve.from_db(-20.0), volume!(10));
+///
+/// // 3 dB louder
+/// assert_eq!(curve.step(volume!(10), 3.0), volume!(14));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct VolumeCurve<'a> {
+    points: &'a [(Volume, f32)],
+}
+
+impl Default for VolumeCurve<'static> {
+    /// The curve if the volume is linear in amplitude, from -40 dB at volume 1 to 0 dB at volume
+    /// 100.
+    fn default() -> Self {
+        VolumeCurve {
+            points: &LINEAR_AMPLITUDE,
+        }
+    }
+}
+
+impl<'a> VolumeCurve<'a> {
+    /// Create a curve from a taper table of volumes and their gain in dB, e.g. measured on the
+    /// amplifier.
+    ///
+    /// If there are less than two points, the volumes or the gains are not strictly increasing, a
+    /// volume is 0 or a gain is not finite then an [Error::InvalidCurve] error is returned.
+    pub fn new(points: &'a [(Volume, f32)]) -> Result<Self, Error> {
+        let valid = points.len() >= 2
+            && points[0].0.get() > 0
+            && points.iter().all(|(_, db)| db.is_finite())
+            && points
+                .windows(2)
+                .all(|pair| pair[0].0.get() < pair[1].0.get() && pair[0].1 < pair[1].1);
+
+        if valid {
+            Ok(VolumeCurve { points })
+        } else {
+            Err(Error::InvalidCurve)
+        }
+    }
+
+    /// The approximate gain in dB at `volume`.
+    pub fn to_db(&self, volume: Volume) -> f32 {
+        let value = volume.get();
+        if value == 0 {
+            return f32::NEG_INFINITY;
+        }
+
+        let (first_volume, first_db) = self.points[0];
+        if value <= first_volume.get() {
+            return first_db;
+        }
+
+        for pair in self.points.windows(2) {
+            let ((low, low_db), (high, high_db)) = (pair[0], pair[1]);
+            if value <= high.get() {
+                let fraction = (value - low.get()) as f32 / (high.get() - low.get()) as f32;
+                return low_db + fraction * (high_db - low_db);
+            }
+        }
+
+        self.max_db()
+    }
+
+    /// The volume with the gain closest to `db`. `f32::NEG_INFINITY` and NaN map to volume 0.
+    pub fn from_db(&self, db: f32) -> Volume {
+        if db.is_nan() || db == f32::NEG_INFINITY {
+            return Volume(0);
+        }
+
+        let mut closest = Volume(Volume::MAX);
+        let mut closest_distance = f32::INFINITY;
+        for volume in Volume::iter_range().skip(1) {
+            let distance = distance(self.to_db(volume), db);
+            if distance < closest_distance {
+                closest = volume;
+                closest_distance = distance;
+            }
+        }
+
+        closest
+    }
+
+    /// Change `volume` by `delta_db` and return the new volume, stopping at the ends of the curve.
+    ///
+    /// The volume always changes by at least one step if `delta_db` is not 0, so that small steps
+    /// are not lost where the curve is steep. Stepping down from the first point of the curve mutes
+    /// with volume 0, stepping up from volume 0 restarts at volume 1.
+    pub fn step(&self, volume: Volume, delta_db: f32) -> Volume {
+        if delta_db == 0.0 || delta_db.is_nan() {
+            return volume;
+        }
+
+        let stepped = self.from_db(self.to_db(volume) + delta_db);
+        if delta_db > 0.0 {
+            Volume(stepped.get().max(volume.saturating_add(1).get()))
+        } else if volume.get() <= self.points[0].0.get() {
+            // The gain is flat below the first point, so the next step down mutes
+            Volume(0)
+        } else {
+            Volume(stepped.get().min(volume.get() - 1))
+        }
+    }
+
+    /// The position of `volume` between the quietest and the loudest gain of the curve, from 0.0 to
+    /// 1.0 in equal dB. Volume 0 is at 0.0.
+    pub fn to_position(&self, volume: Volume) -> f32 {
+        if volume.get() == 0 {
+            return 0.0;
+        }
+
+        (self.to_db(volume) - self.min_db()) / (self.max_db() - self.min_db())
+    }
+
+    /// The volume at a `position` between the quietest and the loudest gain of the curve, e.g. of a
+    /// rotary encoder. Positions outside of 0.0 to 1.0 are clamped, NaN is treated as 0.0.
+    pub fn from_position(&self, position: f32) -> Volume {
+        let position = if position.is_nan() {
+            0.0
+        } else {
+            position.clamp(0.0, 1.0)
+        };
+
+        self.from_db(self.min_db() + position * (self.max_db() - self.min_db()))
+    }
+
+    fn min_db(&self) -> f32 {
+        self.points[0].1
+    }
+
+    fn max_db(&self) -> f32 {
+        self.points[self.points.len() - 1].1
+    }
+}
+
+// The absolute difference, `f32::abs` is not available in `core` on older compilers
+fn distance(a: f32, b: f32) -> f32 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
+// The tone setting closest to `db`, rounding half away from zero
+fn tone_from_db<T: ScalarParameter>(db: f32) -> T {
+    let steps = db / TONE_STEP_DB;
+    let rounded = if steps < 0.0 {
+        steps - 0.5
+    } else {
+        steps + 0.5
+    };
+    // `as` saturates and maps NaN to 0
+    T::saturating_new(rounded as i8)
+}
+
+impl Bass {
+    /// The approximate gain of the bass filter in dB, see [TONE_STEP_DB].
+    pub fn to_db(&self) -> f32 {
+        self.get() as f32 * TONE_STEP_DB
+    }
+
+    /// The bass setting closest to a gain of `db`, stopping at the limits of the range.
+    pub fn from_db(db: f32) -> Self {
+        tone_from_db(db)
+    }
+}
+
+impl Treble {
+    /// The approximate gain of the treble filter in dB, see [TONE_STEP_DB].
+    pub fn to_db(&self) -> f32 {
+        self.get() as f32 * TONE_STEP_DB
+    }
+
+    /// The treble setting closest to a gain of `db`, stopping at the limits of the range.
+    pub fn from_db(db: f32) -> Self {
+        tone_from_db(db)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn default_curve() {
+        let curve = VolumeCurve::default();
+
+        assert_eq!(curve.to_db(Volume(0)), f32::NEG_INFINITY);
+        assert_eq!(curve.to_db(Volume(1)), -40.0);
+        assert_eq!(curve.to_db(Volume(100)), 0.0);
+        assert_eq!(curve.to_db(Volume(90)), -0.97);
+
+        for volume in Volume::iter_range() {
+            assert_eq!(curve.from_db(curve.to_db(volume)), volume);
+        }
+        assert_eq!(curve.from_db(-100.0), Volume(1));
+        assert_eq!(curve.from_db(6.0), Volume(100));
+        assert_eq!(curve.from_db(f32::NAN), Volume(0));
+    }
+
+    #[test]
+    fn custom_curve() {
+        let points = [(Volume(10), -30.0), (Volume(100), -3.0)];
+        let curve = VolumeCurve::new(&points).unwrap();
+
+        assert_eq!(curve.to_db(Volume(5)), -30.0);
+        assert_eq!(curve.to_db(Volume(40)), -21.0);
+        assert_eq!(curve.from_db(0.0), Volume(100));
+
+        assert!(matches!(
+            VolumeCurve::new(&points[..1]),
+            Err(Error::InvalidCurve)
+        ));
+        for invalid in [
+            [(Volume(0), -30.0), (Volume(100), 0.0)],
+            [(Volume(50), -30.0), (Volume(40), 0.0)],
+            [(Volume(10), 0.0), (Volume(100), -30.0)],
+            [(Volume(10), f32::NEG_INFINITY), (Volume(100), 0.0)],
+        ] {
+            assert!(matches!(
+                VolumeCurve::new(&invalid),
+                Err(Error::InvalidCurve)
+            ));
+        }
+    }
+
+    #[test]
+    fn equal_loudness_steps() {
+        let curve = VolumeCurve::default();
+
+        // 3 dB is a large step at the top and a small one at the bottom of the scale
+        assert_eq!(curve.step(Volume(100), -3.0), Volume(71));
+        assert_eq!(curve.step(Volume(4), -3.0), Volume(3));
+        assert_eq!(curve.step(Volume(100), 3.0), Volume(100));
+
+        // The volume always moves
+        assert_eq!(curve.step(Volume(99), -0.01), Volume(98));
+        assert_eq!(curve.step(Volume(0), 0.5), Volume(1));
+        assert_eq!(curve.step(Volume(1), -3.0), Volume(0));
+        assert_eq!(curve.step(Volume(0), -3.0), Volume(0));
+        assert_eq!(curve.step(Volume(50), 0.0), Volume(50));
+    }
+
+    #[test]
+    fn positions() {
+        let curve = VolumeCurve::default();
+
+        assert_eq!(curve.to_position(Volume(0)), 0.0);
+        assert_eq!(curve.to_position(Volume(1)), 0.0);
+        assert_eq!(curve.to_position(Volume(10)), 0.5);
+        assert_eq!(curve.to_position(Volume(100)), 1.0);
+
+        assert_eq!(curve.from_position(0.5), Volume(10));
+        assert_eq!(curve.from_position(2.0), Volume(100));
+        assert_eq!(curve.from_position(f32::NAN), Volume(1));
+    }
+
+    #[test]
+    fn tone_db() {
+        assert_eq!(Bass(-4).to_db(), -4.0);
+        assert_eq!(Treble(10).to_db(), 10.0);
+
+        assert_eq!(Bass::from_db(2.4), Bass(2));
+        assert_eq!(Bass::from_db(-2.5), Bass(-3));
+        assert_eq!(Treble::from_db(40.0), Treble(10));
+        assert_eq!(Treble::from_db(-1000.0), Treble(-10));
+        assert_eq!(Treble::from_db(f32::NAN), Treble(0));
+    }
+}
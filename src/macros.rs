@@ -0,0 +1,91 @@
+//! Macros for parameter values that are checked at compile time, e.g. [volume!].
+//!
+//! The value is evaluated in a `const` item, so a value outside of the range of the parameter is a
+//! compile error instead of an [Error::OutOfRange](crate::Error::OutOfRange) at run time.
+
+/// A [Volume](crate::Volume) that is checked at compile time.
+///
+/// # Example
+/// ```
+/// use up2stream_uart::{volume, Volume};
+///
+/// const DEFAULT_VOLUME: Volume = volume!(30);
+///
+/// assert_eq!(DEFAULT_VOLUME, Volume::new(30).unwrap());
+/// ```
+///
+/// A value out of range fails to compile:
+/// ```compile_fail
+/// let volume = up2stream_uart::volume!(101);
+/// ```
+#[macro_export]
+macro_rules! volume {
+    ($value:expr) => {{
+        const VOLUME: $crate::Volume = $crate::Volume::new_const($value);
+        VOLUME
+    }};
+}
+
+/// A [Bass](crate::Bass) that is checked at compile time, see [volume!].
+///
+/// ```compile_fail
+/// let bass = up2stream_uart::bass!(-11);
+/// ```
+#[macro_export]
+macro_rules! bass {
+    ($value:expr) => {{
+        const BASS: $crate::Bass = $crate::Bass::new_const($value);
+        BASS
+    }};
+}
+
+/// A [Treble](crate::Treble) that is checked at compile time, see [volume!].
+///
+/// ```compile_fail
+/// let treble = up2stream_uart::treble!(11);
+/// ```
+#[macro_export]
+macro_rules! treble {
+    ($value:expr) => {{
+        const TREBLE: $crate::Treble = $crate::Treble::new_const($value);
+        TREBLE
+    }};
+}
+
+/// A [PlayPreset](crate::PlayPreset) that is checked at compile time, see [volume!].
+///
+/// ```compile_fail
+/// let preset = up2stream_uart::play_preset!(11);
+/// ```
+#[macro_export]
+macro_rules! play_preset {
+    ($value:expr) => {{
+        const PLAY_PRESET: $crate::PlayPreset = $crate::PlayPreset::new_const($value);
+        PLAY_PRESET
+    }};
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Bass, PlayPreset, Treble, Volume};
+
+    const PRESETS: [(Volume, Bass, Treble); 2] = [
+        (volume!(20), bass!(-10), treble!(3)),
+        (volume!(100), bass!(10), treble!(-10)),
+    ];
+
+    #[test]
+    fn checked_constants() {
+        assert_eq!(PRESETS[0], (Volume(20), Bass(-10), Treble(3)));
+        assert_eq!(PRESETS[1].0, Volume::new(100).unwrap());
+        assert_eq!(play_preset!(0), PlayPreset(0));
+        assert_eq!(Volume::new_const(55), Volume(55));
+    }
+
+    #[test]
+    #[should_panic]
+    fn new_const_at_run_time() {
+        let value = core::hint::black_box(-1);
+        Volume::new_const(value);
+    }
+}
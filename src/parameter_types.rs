@@ -2,6 +2,7 @@
 // As the parameter-types file is getting large, put each type in a seperate file
 // - together with test code - and reexport from parameter-types.
 
+use core::marker::PhantomData;
 use core::str::FromStr;
 
 use crate::error::Error;
@@ -9,6 +10,9 @@ use crate::error::Error;
 /// Implements the base trait for parameters that represent
 /// a scalar. Required if the inner value of the parameter type is required.
 ///
+/// The valid values are `MIN`, `MIN + STEP`, ... up to `MAX`. The provided methods check or clamp
+/// values to this range, so that the range only needs to be declared once per type.
+///
 /// # Example
 /// ```
 /// use up2stream_uart::{ScalarParameter, Volume};
@@ -19,11 +23,131 @@ use crate::error::Error;
 ///
 /// assert_eq!(23, volume_value);
 ///
+/// assert_eq!(Volume::MAX, 100);
+/// assert_eq!(volume.saturating_add(90), Volume::new(100).unwrap());
+/// assert_eq!(volume.checked_add(90), None);
+/// assert_eq!(Volume::from_percent(50), Volume::new(50).unwrap());
+/// ```
+///
+/// A `STEP` that is not positive fails to compile when the provided methods are used:
+/// ```compile_fail
+/// use up2stream_uart::{CheckedValue, ScalarParameter};
+///
+/// struct NoStep(i8);
+///
+/// impl ScalarParameter for NoStep {
+///     const MIN: i8 = 0;
+///     const MAX: i8 = 4;
+///     const STEP: i8 = 0;
+///
+///     fn get(&self) -> i8 {
+///         self.0
+///     }
+///
+///     fn from_checked(value: CheckedValue<Self>) -> Self {
+///         Self(value.get())
+///     }
+/// }
+///
+/// let value = NoStep::saturating_new(2);
 /// ```
-pub trait ScalarParameter {
+pub trait ScalarParameter: Sized {
+    /// The smallest valid value
+    const MIN: i8;
+    /// The largest valid value
+    const MAX: i8;
+    /// The distance between neighbouring valid values
+    const STEP: i8 = 1;
+
     // Get the contained value
     fn get(&self) -> i8;
 
+    /// Wrap a value that has been checked to be valid. A [CheckedValue] is only created by the
+    /// provided methods, so use [ScalarParameter::checked_new] to create a parameter.
+    fn from_checked(value: CheckedValue<Self>) -> Self;
+
+    /// Create the parameter, returning [Error::OutOfRange] if `value` is not a valid value.
+    fn checked_new(value: i8) -> Result<Self, Error> {
+        let in_range = (Self::MIN..=Self::MAX).contains(&value);
+        if in_range && (value as i16 - Self::MIN as i16) % step::<Self>() == 0 {
+            Ok(Self::from_checked(CheckedValue::new(value)))
+        } else {
+            Err(Error::OutOfRange)
+        }
+    }
+
+    /// Create the parameter from the valid value closest to `value`.
+    fn saturating_new(value: i8) -> Self {
+        from_offset(value as i16 - Self::MIN as i16)
+    }
+
+    /// Add `delta` to the value, returning `None` if the result is not a valid value.
+    fn checked_add(&self, delta: i8) -> Option<Self> {
+        let value = i8::try_from(self.get() as i16 + delta as i16).ok()?;
+        Self::checked_new(value).ok()
+    }
+
+    /// Add `delta` to the value, stopping at `MIN` or `MAX`.
+    fn saturating_add(&self, delta: i8) -> Self {
+        from_offset(self.get() as i16 + delta as i16 - Self::MIN as i16)
+    }
+
+    /// Iterate over all valid values, from `MIN` to `MAX`.
+    fn iter_range() -> ScalarRange<Self> {
+        let _ = step::<Self>();
+        ScalarRange {
+            next: Some(Self::MIN),
+            parameter: PhantomData,
+        }
+    }
+
+    /// The position of the value in the range as a percentage, rounded to the nearest percent.
+    fn to_percent(&self) -> u8 {
+        let span = span::<Self>();
+        if span == 0 {
+            return 0;
+        }
+        let offset = self.get() as i16 - Self::MIN as i16;
+        ((offset * 100 + span / 2) / span) as u8
+    }
+
+    /// Create the parameter from a position in the range as a percentage. Values above 100 are
+    /// treated as 100.
+    fn from_percent(percent: u8) -> Self {
+        let percent = percent.min(100) as i16;
+        from_offset((percent * span::<Self>() + 50) / 100)
+    }
+
+    /// The position of the value in the range, from 0.0 at `MIN` to 1.0 at `MAX`.
+    fn to_normalised(&self) -> f32 {
+        let span = span::<Self>();
+        if span == 0 {
+            return 0.0;
+        }
+        (self.get() as i16 - Self::MIN as i16) as f32 / span as f32
+    }
+
+    /// Create the parameter from a position in the range, from 0.0 at `MIN` to 1.0 at `MAX`.
+    /// Positions outside of this are clamped, NaN is treated as 0.0.
+    fn from_normalised(position: f32) -> Self {
+        let position = if position.is_nan() {
+            0.0
+        } else {
+            position.clamp(0.0, 1.0)
+        };
+        // Rounds, as the product is never negative
+        from_offset((position * span::<Self>() as f32 + 0.5) as i16)
+    }
+
+    /// Parse the parameter from its decimal representation in the UART API.
+    ///
+    /// Returns [Error::InvalidString] if `s` is not a number and [Error::OutOfRange] if it is not
+    /// a valid value.
+    fn from_parameter_str(s: &str) -> Result<Self, Error> {
+        let value = s.parse::<i8>().map_err(|_| Error::InvalidString)?;
+        Self::checked_new(value)
+    }
+
     // Convert the contained value to an array of UTF8 bytes.
     fn to_parameter_str<'a>(&self, buf: &'a mut [u8]) -> &'a [u8] {
         let mut value = self.get(); //self.0.into();
@@ -65,76 +189,169 @@ pub trait ScalarParameter {
     }
 }
 
+/// A value that the provided methods of [ScalarParameter] have checked to be valid for `T`, see
+/// [ScalarParameter::from_checked].
+///
+/// It cannot be created outside of this crate, so that a parameter is never constructed with a value
+/// out of its range.
+#[derive(Debug)]
+pub struct CheckedValue<T> {
+    value: i8,
+    parameter: PhantomData<T>,
+}
+
+impl<T> CheckedValue<T> {
+    // Only called with valid values
+    fn new(value: i8) -> Self {
+        CheckedValue {
+            value,
+            parameter: PhantomData,
+        }
+    }
+
+    /// Get the checked value.
+    pub fn get(&self) -> i8 {
+        self.value
+    }
+}
+
+// The distance between neighbouring values. That it is positive is checked at compile time for
+// each type using the provided methods.
+fn step<T: ScalarParameter>() -> i16 {
+    Step::<T>::VALUE
+}
+
+// Holds the checked step in a constant, which unlike a constant of the trait cannot be overridden
+struct Step<T>(PhantomData<T>);
+
+impl<T: ScalarParameter> Step<T> {
+    const VALUE: i16 = {
+        assert!(T::STEP > 0, "ScalarParameter::STEP must be positive");
+        T::STEP as i16
+    };
+}
+
+// The number of values between `MIN` and `MAX`
+fn span<T: ScalarParameter>() -> i16 {
+    T::MAX as i16 - T::MIN as i16
+}
+
+// The valid value closest to `MIN + offset`
+fn from_offset<T: ScalarParameter>(offset: i16) -> T {
+    let step = step::<T>();
+    let span = span::<T>();
+    let mut offset = (offset.clamp(0, span) + step / 2) / step * step;
+    if offset > span {
+        offset -= step;
+    }
+    T::from_checked(CheckedValue::new((T::MIN as i16 + offset) as i8))
+}
+
+/// Iterator over the valid values of a [ScalarParameter], see [ScalarParameter::iter_range].
+#[derive(Debug, Clone)]
+pub struct ScalarRange<T> {
+    next: Option<i8>,
+    parameter: PhantomData<T>,
+}
+
+impl<T: ScalarParameter> Iterator for ScalarRange<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let value = self.next?;
+        self.next = value
+            .checked_add(step::<T>() as i8)
+            .filter(|next| *next <= T::MAX);
+        Some(T::from_checked(CheckedValue::new(value)))
+    }
+}
+
 /// Represents a volume from 0 to 100.
 #[derive(Debug, PartialEq, Clone, Copy)]
-pub struct Volume(i8);
+pub struct Volume(pub(crate) i8);
 
 impl Volume {
     /// Create a new volume value between 0 and 100.
     ///
     /// If the range is outside of this then an [Error::OutOfRange] error is returned.
     pub fn new(volume: i8) -> Result<Volume, Error> {
-        let range = 0..=100;
-        if range.contains(&volume) {
-            Ok(Self(volume))
-        } else {
-            Err(Error::OutOfRange)
-        }
+        Self::checked_new(volume)
+    }
+
+    /// Create a new value between 0 and 100 in a const context, panicking if it is outside of
+    /// this range. Evaluated in a `const` item this is a compile error, see also [crate::volume!].
+    pub const fn new_const(volume: i8) -> Self {
+        assert!(
+            volume >= <Self as ScalarParameter>::MIN && volume <= <Self as ScalarParameter>::MAX,
+            "volume out of range"
+        );
+        Self(volume)
     }
 }
 
 impl ScalarParameter for Volume {
+    const MIN: i8 = 0;
+    const MAX: i8 = 100;
+
     /// Get the volume as value
     fn get(&self) -> i8 {
         self.0
     }
+
+    fn from_checked(value: CheckedValue<Self>) -> Self {
+        Self(value.get())
+    }
 }
 
 impl FromStr for Volume {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let volume_value = s.parse::<i8>().map_err(|_| Error::InvalidString)?;
-
-        let volume = Volume::new(volume_value)?;
-
-        Ok(volume)
+        Self::from_parameter_str(s)
     }
 }
 
 /// Represents a treble value from -10 to +10.
 #[derive(Debug, PartialEq, Clone, Copy)]
-pub struct Treble(i8); //-10..10
+pub struct Treble(pub(crate) i8); //-10..10
 impl Treble {
     /// Create a new treble value between -10 and 10.
     ///
     /// If the range is outside of this then an [Error::OutOfRange] error is returned.
     pub fn new(treble: i8) -> Result<Self, Error> {
-        let range = -10..=10;
-        if range.contains(&treble) {
-            Ok(Self(treble))
-        } else {
-            Err(Error::OutOfRange)
-        }
+        Self::checked_new(treble)
+    }
+
+    /// Create a new value between -10 and 10 in a const context, panicking if it is outside of
+    /// this range. Evaluated in a `const` item this is a compile error, see also [crate::treble!].
+    pub const fn new_const(treble: i8) -> Self {
+        assert!(
+            treble >= <Self as ScalarParameter>::MIN && treble <= <Self as ScalarParameter>::MAX,
+            "treble out of range"
+        );
+        Self(treble)
     }
 }
 
 impl ScalarParameter for Treble {
+    const MIN: i8 = -10;
+    const MAX: i8 = 10;
+
     /// Get the treble settign as value
     fn get(&self) -> i8 {
         self.0
     }
+
+    fn from_checked(value: CheckedValue<Self>) -> Self {
+        Self(value.get())
+    }
 }
 
 impl FromStr for Treble {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let treble_value = s.parse::<i8>().map_err(|_| Error::InvalidString)?;
-
-        let treble = Treble::new(treble_value)?;
-
-        Ok(treble)
+        Self::from_parameter_str(s)
     }
 }
 
@@ -151,65 +368,81 @@ impl FromStr for Treble {
 /// ```
 ///
 #[derive(Debug, PartialEq, Clone, Copy)]
-pub struct Bass(i8); //-10..10
+pub struct Bass(pub(crate) i8); //-10..10
 impl Bass {
     pub fn new(bass: i8) -> Result<Self, Error> {
-        let range = -10..=10;
-        if range.contains(&bass) {
-            Ok(Self(bass))
-        } else {
-            Err(Error::OutOfRange)
-        }
+        Self::checked_new(bass)
+    }
+
+    /// Create a new value between -10 and 10 in a const context, panicking if it is outside of
+    /// this range. Evaluated in a `const` item this is a compile error, see also [crate::bass!].
+    pub const fn new_const(bass: i8) -> Self {
+        assert!(
+            bass >= <Self as ScalarParameter>::MIN && bass <= <Self as ScalarParameter>::MAX,
+            "bass out of range"
+        );
+        Self(bass)
     }
 }
 
 impl ScalarParameter for Bass {
+    const MIN: i8 = -10;
+    const MAX: i8 = 10;
+
     fn get(&self) -> i8 {
         self.0
     }
+
+    fn from_checked(value: CheckedValue<Self>) -> Self {
+        Self(value.get())
+    }
 }
 
 impl FromStr for Bass {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let bass_value = s.parse::<i8>().map_err(|_| Error::InvalidString)?;
-
-        let bass = Bass::new(bass_value)?;
-
-        Ok(bass)
+        Self::from_parameter_str(s)
     }
 }
 
 /// Represents a play preset setting from 0 to 10.
 #[derive(Debug, PartialEq, Clone, Copy)]
-pub struct PlayPreset(i8); // 0..10
+pub struct PlayPreset(pub(crate) i8); // 0..10
 impl PlayPreset {
     pub fn new(preset: i8) -> Result<Self, Error> {
-        let range = 0..=10;
-        if range.contains(&preset) {
-            Ok(Self(preset))
-        } else {
-            Err(Error::OutOfRange)
-        }
+        Self::checked_new(preset)
+    }
+
+    /// Create a new value between 0 and 10 in a const context, panicking if it is outside of
+    /// this range. Evaluated in a `const` item this is a compile error, see also [crate::play_preset!].
+    pub const fn new_const(preset: i8) -> Self {
+        assert!(
+            preset >= <Self as ScalarParameter>::MIN && preset <= <Self as ScalarParameter>::MAX,
+            "play preset out of range"
+        );
+        Self(preset)
     }
 }
 
 impl ScalarParameter for PlayPreset {
+    const MIN: i8 = 0;
+    const MAX: i8 = 10;
+
     fn get(&self) -> i8 {
         self.0
     }
+
+    fn from_checked(value: CheckedValue<Self>) -> Self {
+        Self(value.get())
+    }
 }
 
 impl FromStr for PlayPreset {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let preset_value = s.parse::<i8>().map_err(|_| Error::InvalidString)?;
-
-        let preset = PlayPreset::new(preset_value)?;
-
-        Ok(preset)
+        Self::from_parameter_str(s)
     }
 }
 
@@ -270,6 +503,7 @@ impl FromStr for Switch {
 }
 
 /// System control for the device
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum SystemControl {
     /// Reboot the device
     Reboot,
@@ -277,8 +511,8 @@ pub enum SystemControl {
     Standby,
     /// Factory reset
     Reset,
-    // Recover all data. This is a version 4 command
-    //Recover,
+    /// Recover all data. Requires [ProtocolVersion::V4](crate::ProtocolVersion::V4)
+    Recover,
 }
 
 impl SystemControl {
@@ -287,7 +521,7 @@ impl SystemControl {
             Self::Reboot => "REBOOT",
             Self::Standby => "STANDBY",
             Self::Reset => "RESET",
-            //Self::Recover => "RECOVER",
+            Self::Recover => "RECOVER",
         };
 
         buf[..parameter.len()].clone_from_slice(&parameter.as_bytes()[..parameter.len()]);
@@ -300,6 +534,20 @@ impl SystemControl {
     }
 }
 
+impl FromStr for SystemControl {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "REBOOT" => Ok(SystemControl::Reboot),
+            "STANDBY" => Ok(SystemControl::Standby),
+            "RESET" => Ok(SystemControl::Reset),
+            "RECOVER" => Ok(SystemControl::Recover),
+            _ => Err(Error::InvalidString),
+        }
+    }
+}
+
 /// Source selection for the device
 // TODO removed unused types
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -368,7 +616,7 @@ impl FromStr for Source {
 }
 
 /// A struct to represent the device status.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub struct DeviceStatus {
     /// The current input source
     pub source: Source,
@@ -392,6 +640,29 @@ pub struct DeviceStatus {
     pub upgrading: bool,
 }
 
+/// Parses the parameters of the `STA` response. Fields after `upgrading` are ignored.
+impl FromStr for DeviceStatus {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split(',');
+        let mut next = || fields.next().ok_or(Error::IllFormedReponse);
+
+        Ok(DeviceStatus {
+            source: Source::from_str(next()?)?,
+            mute: Switch::from_str(next()?)?.to_bool()?,
+            volume: Volume::from_str(next()?)?,
+            treble: Treble::from_str(next()?)?,
+            bass: Bass::from_str(next()?)?,
+            net: Switch::from_str(next()?)?.to_bool()?,
+            internet: Switch::from_str(next()?)?.to_bool()?,
+            playing: Switch::from_str(next()?)?.to_bool()?,
+            led: Switch::from_str(next()?)?.to_bool()?,
+            upgrading: Switch::from_str(next()?)?.to_bool()?,
+        })
+    }
+}
+
 /// Current network playback state
 pub enum Playback {
     Playing,
@@ -399,13 +670,44 @@ pub enum Playback {
 }
 
 /// Left, Right channel or stereo.
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum AudioChannel {
     Left,
     Right,
     Stereo,
 }
 
+impl AudioChannel {
+    pub fn to_parameter_str<'a>(&self, buf: &'a mut [u8]) -> &'a [u8] {
+        let parameter = match self {
+            Self::Left => "L",
+            Self::Right => "R",
+            Self::Stereo => "S",
+        };
+
+        buf[..parameter.len()].clone_from_slice(parameter.as_bytes());
+
+        // Return the slice that has the same number of characters as
+        // the parameter
+        &buf[..parameter.len()]
+    }
+}
+
+impl FromStr for AudioChannel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "L" => Ok(AudioChannel::Left),
+            "R" => Ok(AudioChannel::Right),
+            "S" => Ok(AudioChannel::Stereo),
+            _ => Err(Error::InvalidString),
+        }
+    }
+}
+
 /// Sets the relationship of a device in a mult-room configuration
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum MultiroomState {
     Slave,
     Master,
@@ -413,13 +715,49 @@ pub enum MultiroomState {
 }
 
 /// The onboard LED
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Led {
     On,
     Off,
     Toogle,
 }
 
+impl Led {
+    pub fn to_parameter_str<'a>(&self, buf: &'a mut [u8]) -> &'a [u8] {
+        let switch = match self {
+            Self::On => Switch::On,
+            Self::Off => Switch::Off,
+            Self::Toogle => Switch::Toggle,
+        };
+
+        switch.to_parameter_str(buf)
+    }
+}
+
+impl From<bool> for Led {
+    fn from(value: bool) -> Self {
+        if value {
+            Led::On
+        } else {
+            Led::Off
+        }
+    }
+}
+
+impl FromStr for Led {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Switch::from_str(s)? {
+            Switch::On => Ok(Led::On),
+            Switch::Off => Ok(Led::Off),
+            Switch::Toggle => Ok(Led::Toogle),
+        }
+    }
+}
+
 /// Loop mode for network playback
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum LoopMode {
     RepeatAll,
     RepeatOne,
@@ -428,6 +766,39 @@ pub enum LoopMode {
     Sequence,
 }
 
+impl LoopMode {
+    pub fn to_parameter_str<'a>(&self, buf: &'a mut [u8]) -> &'a [u8] {
+        let parameter = match self {
+            Self::RepeatAll => "REPEATALL",
+            Self::RepeatOne => "REPEATONE",
+            Self::RepeatShuffle => "REPEATSHUFFLE",
+            Self::Shuffle => "SHUFFLE",
+            Self::Sequence => "SEQUENCE",
+        };
+
+        buf[..parameter.len()].clone_from_slice(parameter.as_bytes());
+
+        // Return the slice that has the same number of characters as
+        // the parameter
+        &buf[..parameter.len()]
+    }
+}
+
+impl FromStr for LoopMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "REPEATALL" => Ok(LoopMode::RepeatAll),
+            "REPEATONE" => Ok(LoopMode::RepeatOne),
+            "REPEATSHUFFLE" => Ok(LoopMode::RepeatShuffle),
+            "SHUFFLE" => Ok(LoopMode::Shuffle),
+            "SEQUENCE" => Ok(LoopMode::Sequence),
+            _ => Err(Error::InvalidString),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
 
@@ -754,6 +1125,103 @@ mod test {
         }
     }
 
+    // Even values from -4 to 4
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    struct Even(i8);
+
+    impl ScalarParameter for Even {
+        const MIN: i8 = -4;
+        const MAX: i8 = 4;
+        const STEP: i8 = 2;
+
+        fn get(&self) -> i8 {
+            self.0
+        }
+
+        fn from_checked(value: CheckedValue<Self>) -> Self {
+            Self(value.get())
+        }
+    }
+
+    #[test]
+    fn scalar_saturating() {
+        assert_eq!(Volume::saturating_new(-5), Volume(0));
+        assert_eq!(Volume::saturating_new(127), Volume(100));
+        assert_eq!(Bass::saturating_new(3), Bass(3));
+
+        assert_eq!(Volume(98).saturating_add(5), Volume(100));
+        assert_eq!(Volume(3).saturating_add(-128), Volume(0));
+        assert_eq!(Treble(-9).saturating_add(-1), Treble(-10));
+        assert_eq!(Treble(0).saturating_add(4), Treble(4));
+
+        assert_eq!(Even::saturating_new(1), Even(2));
+        assert_eq!(Even::saturating_new(-1), Even(0));
+        assert_eq!(Even::saturating_new(9), Even(4));
+        assert_eq!(Even(2).saturating_add(1), Even(4));
+    }
+
+    #[test]
+    fn scalar_checked() {
+        assert_eq!(Volume(98).checked_add(2), Some(Volume(100)));
+        assert_eq!(Volume(98).checked_add(3), None);
+        assert_eq!(Volume(100).checked_add(127), None);
+        assert_eq!(Bass(-10).checked_add(-1), None);
+        assert_eq!(PlayPreset(0).checked_add(10), Some(PlayPreset(10)));
+
+        assert_eq!(Even::checked_new(-4).unwrap(), Even(-4));
+        assert!(matches!(Even::checked_new(3), Err(Error::OutOfRange)));
+        assert!(matches!(Even::checked_new(6), Err(Error::OutOfRange)));
+        assert_eq!(Even(0).checked_add(1), None);
+    }
+
+    #[test]
+    fn scalar_iter_range() {
+        assert_eq!(Volume::iter_range().count(), 101);
+        assert_eq!(Volume::iter_range().last(), Some(Volume(100)));
+
+        let treble: Vec<i8> = Treble::iter_range().map(|treble| treble.get()).collect();
+        assert_eq!(treble, (-10..=10).collect::<Vec<i8>>());
+
+        let even: Vec<Even> = Even::iter_range().collect();
+        assert_eq!(even, [Even(-4), Even(-2), Even(0), Even(2), Even(4)]);
+    }
+
+    #[test]
+    fn scalar_percent() {
+        assert_eq!(Volume(42).to_percent(), 42);
+        assert_eq!(Volume::from_percent(42), Volume(42));
+        assert_eq!(Volume::from_percent(255), Volume(100));
+
+        assert_eq!(Bass(-10).to_percent(), 0);
+        assert_eq!(Bass(0).to_percent(), 50);
+        assert_eq!(Bass(1).to_percent(), 55);
+        assert_eq!(Bass::from_percent(100), Bass(10));
+        assert_eq!(Bass::from_percent(52), Bass(0));
+
+        assert_eq!(Even::from_percent(60), Even(2));
+
+        for treble in Treble::iter_range() {
+            assert_eq!(Treble::from_percent(treble.to_percent()), treble);
+        }
+    }
+
+    #[test]
+    fn scalar_normalised() {
+        assert_eq!(Volume(25).to_normalised(), 0.25);
+        assert_eq!(Treble(-10).to_normalised(), 0.0);
+        assert_eq!(Treble(10).to_normalised(), 1.0);
+
+        assert_eq!(Volume::from_normalised(0.333), Volume(33));
+        assert_eq!(Bass::from_normalised(0.5), Bass(0));
+        assert_eq!(Bass::from_normalised(-3.0), Bass(-10));
+        assert_eq!(Bass::from_normalised(7.0), Bass(10));
+        assert_eq!(Bass::from_normalised(f32::NAN), Bass(-10));
+
+        for volume in Volume::iter_range() {
+            assert_eq!(Volume::from_normalised(volume.to_normalised()), volume);
+        }
+    }
+
     #[test]
     fn switch_from() {
         let mut switch: Switch = Switch::from(true);
@@ -810,10 +1278,41 @@ mod test {
         assert_eq!(SystemControl::Reset.to_parameter_str(&mut buf), b"RESET");
 
         // Device API version 4 functionality
-        // assert_eq!(
-        //     SystemControl::Recover.to_parameter_str(&mut buf),
-        //     b"RECOVER"
-        // );
+        assert_eq!(
+            SystemControl::Recover.to_parameter_str(&mut buf),
+            b"RECOVER"
+        );
+    }
+
+    #[test]
+    fn audio_channel_parameter_str() {
+        let mut buf = [0; 1];
+        assert_eq!(AudioChannel::Left.to_parameter_str(&mut buf), b"L");
+        assert_eq!(AudioChannel::Right.to_parameter_str(&mut buf), b"R");
+        assert_eq!(AudioChannel::Stereo.to_parameter_str(&mut buf), b"S");
+
+        assert_eq!(AudioChannel::from_str("L").unwrap(), AudioChannel::Left);
+        assert_eq!(AudioChannel::from_str("R").unwrap(), AudioChannel::Right);
+        assert_eq!(AudioChannel::from_str("S").unwrap(), AudioChannel::Stereo);
+        assert!(AudioChannel::from_str("X").is_err());
+    }
+
+    #[test]
+    fn loop_mode_parameter_str() {
+        let modes = [
+            (LoopMode::RepeatAll, "REPEATALL"),
+            (LoopMode::RepeatOne, "REPEATONE"),
+            (LoopMode::RepeatShuffle, "REPEATSHUFFLE"),
+            (LoopMode::Shuffle, "SHUFFLE"),
+            (LoopMode::Sequence, "SEQUENCE"),
+        ];
+
+        let mut buf = [0; 13];
+        for (mode, parameter) in modes {
+            assert_eq!(mode.to_parameter_str(&mut buf), parameter.as_bytes());
+            assert_eq!(LoopMode::from_str(parameter).unwrap(), mode);
+        }
+        assert!(LoopMode::from_str("REPEAT").is_err());
     }
 
     #[test]
@@ -0,0 +1,262 @@
+//! Pipelined queries, see [Up2Stream::query_many].
+
+use core::str::FromStr;
+
+use arrayvec::ArrayString;
+use embedded_hal::serial::Read;
+
+use crate::error::Error;
+use crate::serial::Transmit;
+use crate::{
+    Bass, DeviceStatus, Idle, Source, Switch, Treble, Up2Stream, Volume, COMMAND_AUD, COMMAND_BAS,
+    COMMAND_MUT, COMMAND_SRC, COMMAND_STATUS, COMMAND_TRE, COMMAND_VOL, COMMAND_WWW,
+    PARAMETER_DELIMITER, PARAMETER_START, TERMINATOR,
+};
+
+// Longest command name accepted in a response frame
+pub(crate) const MAX_COMMAND_LENGTH: usize = 8;
+
+/// The number of consecutive polls without data after which pipelined queries time out if no
+/// timeout is configured with [Up2StreamBuilder::timeout_ms](crate::Up2StreamBuilder::timeout_ms)
+/// or [Up2StreamBuilder::timeout_polls](crate::Up2StreamBuilder::timeout_polls).
+///
+/// Unlike a single query, several outstanding replies cannot be told apart from a dropped one, so
+/// pipelined reads are never left waiting indefinitely.
+pub const DEFAULT_PIPELINE_TIMEOUT_POLLS: u32 = 1_000_000;
+
+// A frame that has been partially read by a read that does not wait for data. Its parameters are
+// kept in the response buffer of the driver.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct PartialFrame {
+    command: ArrayString<MAX_COMMAND_LENGTH>,
+    in_parameters: bool,
+}
+
+/// A query that can be sent together with others using [Up2Stream::query_many].
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Query {
+    /// See [Up2Stream::status]
+    Status,
+    /// See [Up2Stream::internet_connection]
+    InternetConnection,
+    /// See [Up2Stream::audio_out]
+    AudioOut,
+    /// See [Up2Stream::input_source]
+    InputSource,
+    /// See [Up2Stream::volume]
+    Volume,
+    /// See [Up2Stream::mute_status]
+    Mute,
+    /// See [Up2Stream::bass]
+    Bass,
+    /// See [Up2Stream::treble]
+    Treble,
+}
+
+/// The typed reply to a [Query].
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Reply {
+    Status(DeviceStatus),
+    InternetConnection(bool),
+    AudioOut(bool),
+    InputSource(Source),
+    Volume(Volume),
+    Mute(bool),
+    Bass(Bass),
+    Treble(Treble),
+}
+
+impl Query {
+    /// The name of the command, which is also the start of the response.
+    pub fn command(&self) -> &'static str {
+        match self {
+            Self::Status => COMMAND_STATUS,
+            Self::InternetConnection => COMMAND_WWW,
+            Self::AudioOut => COMMAND_AUD,
+            Self::InputSource => COMMAND_SRC,
+            Self::Volume => COMMAND_VOL,
+            Self::Mute => COMMAND_MUT,
+            Self::Bass => COMMAND_BAS,
+            Self::Treble => COMMAND_TRE,
+        }
+    }
+
+    /// Parse the parameters of the response.
+    fn parse(&self, response: &str) -> Result<Reply, Error> {
+        let switch = |response: &str| Switch::from_str(response)?.to_bool();
+
+        match self {
+            Self::Status => DeviceStatus::from_str(response).map(Reply::Status),
+            Self::InternetConnection if response.len() != 1 => Err(Error::IllFormedReponse),
+            Self::InternetConnection => switch(response).map(Reply::InternetConnection),
+            Self::AudioOut if response.len() > 1 => Err(Error::IllFormedReponse),
+            Self::AudioOut => switch(response).map(Reply::AudioOut),
+            Self::InputSource => Source::from_str(response).map(Reply::InputSource),
+            Self::Volume => Volume::from_str(response).map(Reply::Volume),
+            Self::Mute => switch(response).map(Reply::Mute),
+            Self::Bass => Bass::from_str(response).map(Reply::Bass),
+            Self::Treble => Treble::from_str(response).map(Reply::Treble),
+        }
+    }
+}
+
+impl<R, W, const N: usize> Up2Stream<R, W, N>
+where
+    R: Read<u8>,
+    W: Transmit<R>,
+{
+    /// Send several queries back to back and return the reply to each query, in the same order.
+    ///
+    /// Replies are matched to the queries by their command name, so they may arrive in any order.
+    /// Frames that do not answer an outstanding query, and line noise, are skipped. If no frame is
+    /// received within the timeout configured with [Up2StreamBuilder::timeout_ms](crate::Up2StreamBuilder::timeout_ms)
+    /// or [Up2StreamBuilder::timeout_polls](crate::Up2StreamBuilder::timeout_polls), the queries
+    /// still without a reply are resent up to the configured number of retries, after which their
+    /// result is [Error::Timeout]. Without a configured timeout [DEFAULT_PIPELINE_TIMEOUT_POLLS] is
+    /// used, so a dropped reply does not block forever.
+    ///
+    /// # Example
+    /// ```
+    /// use up2stream_uart::{Up2Stream, Query, Reply, Volume, Bass};
+    /// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+    /// # let mut serial = SerialMock::new(&[
+    /// #     SerialTransaction::write(b';'),
+    /// #     SerialTransaction::write_many(b"VOL;"),
+    /// #     SerialTransaction::flush(),
+    /// #     SerialTransaction::write_many(b"BAS;"),
+    /// #     SerialTransaction::flush(),
+    /// #     SerialTransaction::read_many(b"BAS:-2;VOL:40;"),
+    /// # ]);
+    /// # let mut up2stream_driver = Up2Stream::new_combined(serial.clone());
+    /// let [volume, bass] = up2stream_driver.query_many(&[Query::Volume, Query::Bass]);
+    ///
+    /// assert_eq!(volume.unwrap(), Reply::Volume(Volume::new(40).unwrap()));
+    /// assert_eq!(bass.unwrap(), Reply::Bass(Bass::new(-2).unwrap()));
+    /// # serial.done();
+    /// ```
+    pub fn query_many<const Q: usize>(
+        &mut self,
+        queries: &[Query; Q],
+    ) -> [Result<Reply, Error>; Q] {
+        let mut replies: [Option<Result<Reply, Error>>; Q] = core::array::from_fn(|_| None);
+        let mut attempt = 0;
+
+        loop {
+            // Send each outstanding command once
+            for (index, query) in queries.iter().enumerate() {
+                let sent_before = queries[..index]
+                    .iter()
+                    .zip(&replies)
+                    .any(|(other, reply)| other == query && reply.is_none());
+
+                if replies[index].is_some() || sent_before {
+                    continue;
+                }
+
+                if self.send_command(query.command(), b"").is_err() {
+                    for (other, reply) in queries.iter().zip(replies.iter_mut()) {
+                        if other == query {
+                            *reply = Some(Err(Error::SendCommand));
+                        }
+                    }
+                }
+            }
+
+            while replies.iter().any(Option::is_none) {
+                match self.read_frame(true) {
+                    Ok(command) => {
+                        for (query, reply) in queries.iter().zip(replies.iter_mut()) {
+                            if reply.is_none() && query.command() == command.as_str() {
+                                *reply = Some(query.parse(self.response.as_str()));
+                            }
+                        }
+                    }
+                    Err(Error::Timeout) => break,
+                    Err(_) => {
+                        for reply in replies.iter_mut().filter(|reply| reply.is_none()) {
+                            *reply = Some(Err(Error::Read));
+                        }
+                    }
+                }
+            }
+
+            if replies.iter().all(Option::is_some) || attempt >= self.config.retries {
+                break;
+            }
+            attempt += 1;
+        }
+
+        replies.map(|reply| reply.unwrap_or(Err(Error::Timeout)))
+    }
+
+    // Read the next well formed frame `NAME:PARAMETERS;`, returning the name of the command
+    // with the parameters in the response buffer. Ill formed frames are skipped. If `wait` is
+    // false, Error::Timeout is returned as soon as no data is available and a partially read
+    // frame is kept to be resumed by the next call, otherwise Error::Timeout is returned after
+    // the configured timeout or DEFAULT_PIPELINE_TIMEOUT_POLLS.
+    pub(crate) fn read_frame(
+        &mut self,
+        wait: bool,
+    ) -> Result<ArrayString<MAX_COMMAND_LENGTH>, Error> {
+        let PartialFrame {
+            mut command,
+            mut in_parameters,
+        } = core::mem::take(&mut self.partial_frame);
+        let mut idle = Idle::default();
+        let timeout_polls = self
+            .config
+            .timeout_polls
+            .unwrap_or(DEFAULT_PIPELINE_TIMEOUT_POLLS);
+
+        if command.is_empty() {
+            self.response.clear();
+        }
+
+        loop {
+            let byte = match self.reader.read() {
+                Ok(byte) => byte,
+                Err(nb::Error::WouldBlock) if !wait => {
+                    self.partial_frame = PartialFrame {
+                        command,
+                        in_parameters,
+                    };
+                    return Err(Error::Timeout);
+                }
+                Err(nb::Error::WouldBlock) => {
+                    if self.timed_out(&mut idle, Some(timeout_polls)) {
+                        return Err(Error::Timeout);
+                    }
+                    continue;
+                }
+                Err(nb::Error::Other(_)) => return Err(Error::Read),
+            };
+            idle = Idle::default();
+
+            let valid = match (in_parameters, byte) {
+                (false, c) if c.is_ascii_alphanumeric() => command.try_push(c as char).is_ok(),
+                (false, PARAMETER_START) if !command.is_empty() => {
+                    in_parameters = true;
+                    true
+                }
+                (true, TERMINATOR) => return Ok(command),
+                (true, c)
+                    if c.is_ascii_alphanumeric()
+                        || matches!(c, b'-' | b'+' | b'.' | PARAMETER_DELIMITER) =>
+                {
+                    self.response.try_push(c as char).is_ok()
+                }
+                _ => false,
+            };
+
+            if !valid {
+                // Start again with the next frame, which may begin with this character
+                command.clear();
+                self.response.clear();
+                in_parameters = false;
+                if byte.is_ascii_alphanumeric() {
+                    command.push(byte as char);
+                }
+            }
+        }
+    }
+}
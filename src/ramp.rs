@@ -0,0 +1,445 @@
+//! Gradual changes of the volume, see [Up2Stream::ramp_volume] and [VolumeRamp].
+
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::serial::Read;
+
+use crate::clock::Clock;
+use crate::error::Error;
+use crate::serial::Transmit;
+use crate::{ScalarParameter, Up2Stream, Volume};
+
+pub(crate) const SILENT: Volume = Volume(0);
+
+// The volume at `elapsed_ms` of a linear ramp
+fn interpolate(start: Volume, target: Volume, elapsed_ms: u32, duration_ms: u32) -> Volume {
+    if elapsed_ms >= duration_ms {
+        return target;
+    }
+
+    let difference = target.get() as i64 - start.get() as i64;
+    let step = difference * elapsed_ms as i64 / duration_ms as i64;
+    Volume((start.get() as i64 + step) as i8)
+}
+
+impl<R, W, const N: usize> Up2Stream<R, W, N>
+where
+    R: Read<u8>,
+    W: Transmit<R>,
+{
+    /// Change the volume from the current volume to `target` in steps of one over `duration_ms`
+    /// milliseconds, blocking until `target` is reached or the ramp is cancelled. Returns the
+    /// volume the ramp stopped at, which is `target` unless it was cancelled.
+    ///
+    /// `cancel` is called before each step, e.g. to check if a button has been pressed, and the
+    /// ramp stops, leaving the volume where it is, as soon as it returns `true`. Pass `|| false`
+    /// for a ramp that always completes. Use a [VolumeRamp] to ramp the volume without blocking.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use up2stream_uart::{Up2Stream, Volume};
+    /// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+    /// # use embedded_hal_mock::delay::MockNoop;
+    /// # let mut uart = SerialMock::new(&[SerialTransaction::read(b';')]);
+    /// # let mut up2stream_driver = Up2Stream::new_combined(uart);
+    /// # let mut delay = MockNoop::new();
+    /// # let button_pressed = || false;
+    /// // Turn the volume up to 80 within 2 s, unless the button is pressed
+    /// let volume = up2stream_driver
+    ///     .ramp_volume(Volume::new(80).unwrap(), 2000, &mut delay, button_pressed)
+    ///     .unwrap();
+    /// ```
+    pub fn ramp_volume<D: DelayMs<u32>, F: FnMut() -> bool>(
+        &mut self,
+        target: Volume,
+        duration_ms: u32,
+        delay: &mut D,
+        cancel: F,
+    ) -> Result<Volume, Error> {
+        let start = self.volume()?;
+
+        self.ramp_volume_from(start, target, duration_ms, delay, cancel)
+    }
+
+    /// Ramp the volume down to 0 over `duration_ms` milliseconds, see [Up2Stream::ramp_volume].
+    /// Returns the volume before the fade, e.g. to fade in again with [Up2Stream::fade_in].
+    ///
+    /// If `cancel` returns `true` before a step, the fade stops and the volume is left where it is.
+    pub fn fade_out<D: DelayMs<u32>, F: FnMut() -> bool>(
+        &mut self,
+        duration_ms: u32,
+        delay: &mut D,
+        cancel: F,
+    ) -> Result<Volume, Error> {
+        let start = self.volume()?;
+
+        self.ramp_volume_from(start, SILENT, duration_ms, delay, cancel)?;
+        Ok(start)
+    }
+
+    /// Set the volume to 0 and ramp it up to `target` over `duration_ms` milliseconds, see
+    /// [Up2Stream::ramp_volume]. Returns the volume the fade stopped at, which is `target` unless
+    /// `cancel` returned `true` before a step.
+    pub fn fade_in<D: DelayMs<u32>, F: FnMut() -> bool>(
+        &mut self,
+        target: Volume,
+        duration_ms: u32,
+        delay: &mut D,
+        cancel: F,
+    ) -> Result<Volume, Error> {
+        self.set_volume(SILENT)?;
+
+        self.ramp_volume_from(SILENT, target, duration_ms, delay, cancel)
+    }
+
+    pub(crate) fn ramp_volume_from<D: DelayMs<u32>, F: FnMut() -> bool>(
+        &mut self,
+        start: Volume,
+        target: Volume,
+        duration_ms: u32,
+        delay: &mut D,
+        mut cancel: F,
+    ) -> Result<Volume, Error> {
+        let steps = (target.get() - start.get()).unsigned_abs() as u32;
+        let mut elapsed_ms = 0;
+        let mut volume = start;
+
+        for step in 1..=steps {
+            // Spread the rounding of the step duration over the ramp
+            let next_ms = (duration_ms as u64 * step as u64 / steps as u64) as u32;
+            delay.delay_ms(next_ms - elapsed_ms);
+            elapsed_ms = next_ms;
+
+            if cancel() {
+                break;
+            }
+            volume = interpolate(start, target, step, steps);
+            self.set_volume(volume)?;
+        }
+
+        Ok(volume)
+    }
+}
+
+/// A non-blocking ramp of the volume, driven by [VolumeRamp::poll] from the main loop.
+///
+/// The volume changes linearly from the start volume to the target over the duration of the ramp,
+/// as measured by the [Clock]. Each poll sends the volume for the current time if it differs from
+/// the volume sent last. The ramp can be cancelled at any time by dropping it or with
+/// [VolumeRamp::cancel], which leaves the volume where it is.
+///
+/// # Example
+/// ```
+/// use up2stream_uart::{Up2Stream, Volume, VolumeRamp};
+/// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+/// # let mut serial = SerialMock::new(&[
+/// #     SerialTransaction::write(b';'),
+/// #     SerialTransaction::write_many(b"VOL;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::read_many(b"VOL:40;"),
+/// #     SerialTransaction::write_many(b"VOL:30;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::write_many(b"VOL:20;"),
+/// #     SerialTransaction::flush(),
+/// # ]);
+/// # let mut up2stream_device = Up2Stream::new_combined(serial.clone());
+/// # let mut timer = 0;
+/// let clock = || { timer += 500; timer };
+///
+/// // Turn the volume down to 20 within 1 s
+/// let mut ramp = VolumeRamp::new(&mut up2stream_device, Volume::new(20).unwrap(), 1000, clock).unwrap();
+///
+/// loop {
+///     // Called from the main loop
+///     match ramp.poll(&mut up2stream_device) {
+///         Ok(()) => break,
+///         Err(nb::Error::WouldBlock) => (),
+///         Err(nb::Error::Other(e)) => panic!("{:?}", e),
+///     }
+/// }
+/// # serial.done();
+/// ```
+pub struct VolumeRamp<C> {
+    clock: C,
+    start: Volume,
+    target: Volume,
+    start_ms: u32,
+    duration_ms: u32,
+    current: Volume,
+}
+
+impl<C: Clock> VolumeRamp<C> {
+    /// Start ramping the volume of the board controlled by `driver` from the current volume to
+    /// `target` over `duration_ms` milliseconds according to `clock`.
+    pub fn new<R, W, const N: usize>(
+        driver: &mut Up2Stream<R, W, N>,
+        target: Volume,
+        duration_ms: u32,
+        clock: C,
+    ) -> Result<Self, Error>
+    where
+        R: Read<u8>,
+        W: Transmit<R>,
+    {
+        let start = driver.volume()?;
+
+        Ok(Self::from_volume(start, target, duration_ms, clock))
+    }
+
+    /// Start ramping the volume down to 0 over `duration_ms` milliseconds. The volume before the
+    /// fade is the [start](VolumeRamp::start) of the ramp.
+    pub fn fade_out<R, W, const N: usize>(
+        driver: &mut Up2Stream<R, W, N>,
+        duration_ms: u32,
+        clock: C,
+    ) -> Result<Self, Error>
+    where
+        R: Read<u8>,
+        W: Transmit<R>,
+    {
+        Self::new(driver, SILENT, duration_ms, clock)
+    }
+
+    /// Set the volume to 0 and start ramping it up to `target` over `duration_ms` milliseconds.
+    pub fn fade_in<R, W, const N: usize>(
+        driver: &mut Up2Stream<R, W, N>,
+        target: Volume,
+        duration_ms: u32,
+        clock: C,
+    ) -> Result<Self, Error>
+    where
+        R: Read<u8>,
+        W: Transmit<R>,
+    {
+        driver.set_volume(SILENT)?;
+
+        Ok(Self::from_volume(SILENT, target, duration_ms, clock))
+    }
+
+    fn from_volume(start: Volume, target: Volume, duration_ms: u32, mut clock: C) -> Self {
+        VolumeRamp {
+            start_ms: clock.now_ms(),
+            clock,
+            start,
+            target,
+            duration_ms,
+            current: start,
+        }
+    }
+
+    /// The volume at the start of the ramp.
+    pub fn start(&self) -> Volume {
+        self.start
+    }
+
+    /// The volume at the end of the ramp.
+    pub fn target(&self) -> Volume {
+        self.target
+    }
+
+    /// The volume last sent to the board.
+    pub fn volume(&self) -> Volume {
+        self.current
+    }
+
+    /// Send the volume for the current time if it changed. Returns `Ok(())` once the target has
+    /// been sent, and `Err(nb::Error::WouldBlock)` while the ramp is in progress.
+    ///
+    /// If the volume cannot be sent, the error is returned and the volume is sent again at the
+    /// next poll.
+    pub fn poll<R, W, const N: usize>(
+        &mut self,
+        driver: &mut Up2Stream<R, W, N>,
+    ) -> nb::Result<(), Error>
+    where
+        R: Read<u8>,
+        W: Transmit<R>,
+    {
+        let elapsed_ms = self.clock.now_ms().wrapping_sub(self.start_ms);
+        let volume = interpolate(self.start, self.target, elapsed_ms, self.duration_ms);
+
+        if volume != self.current {
+            driver.set_volume(volume)?;
+            self.current = volume;
+        }
+
+        if self.current == self.target {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Stop the ramp, leaving the volume as last sent, and give back the clock.
+    pub fn cancel(self) -> (Volume, C) {
+        (self.current, self.clock)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::cell::Cell;
+
+    use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+
+    use crate::sim::Simulator;
+    use crate::{Combined, Up2StreamBuilder};
+
+    // Records the delays
+    struct Delay(Vec<u32>);
+
+    impl DelayMs<u32> for Delay {
+        fn delay_ms(&mut self, ms: u32) {
+            self.0.push(ms);
+        }
+    }
+
+    fn expect_volumes(volumes: &[i8]) -> Vec<SerialTransaction<u8>> {
+        let mut expectations = Vec::new();
+        for volume in volumes {
+            expectations.push(SerialTransaction::write_many(format!("VOL:{};", volume)));
+            expectations.push(SerialTransaction::flush());
+        }
+        expectations
+    }
+
+    #[test]
+    fn interpolation() {
+        let (start, target) = (Volume(10), Volume(0));
+
+        assert_eq!(interpolate(start, target, 0, 100), Volume(10));
+        assert_eq!(interpolate(start, target, 55, 100), Volume(5));
+        assert_eq!(interpolate(start, target, 100, 100), Volume(0));
+        assert_eq!(interpolate(start, target, 0, 0), Volume(0));
+    }
+
+    #[test]
+    fn ramp_volume() {
+        let mut expectations = vec![
+            SerialTransaction::write(b';'),
+            SerialTransaction::write_many(b"VOL;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"VOL:20;"),
+        ];
+        expectations.extend(expect_volumes(&[21, 22, 23]));
+
+        let mut serial = SerialMock::new(&expectations);
+        let mut up2stream_device = Up2Stream::new_combined(serial.clone());
+        let mut delay = Delay(Vec::new());
+
+        let volume = up2stream_device
+            .ramp_volume(Volume(23), 100, &mut delay, || false)
+            .unwrap();
+        assert_eq!(volume, Volume(23));
+        assert_eq!(delay.0, [33, 33, 34]);
+
+        serial.done();
+    }
+
+    #[test]
+    fn fade_out_and_in() {
+        let mut expectations = vec![
+            SerialTransaction::write(b';'),
+            SerialTransaction::write_many(b"VOL;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"VOL:2;"),
+        ];
+        expectations.extend(expect_volumes(&[1, 0, 0, 1, 2]));
+
+        let mut serial = SerialMock::new(&expectations);
+        let mut up2stream_device = Up2Stream::new_combined(serial.clone());
+        let mut delay = Delay(Vec::new());
+
+        let volume = up2stream_device.fade_out(10, &mut delay, || false).unwrap();
+        assert_eq!(volume, Volume(2));
+        up2stream_device
+            .fade_in(volume, 20, &mut delay, || false)
+            .unwrap();
+        assert_eq!(delay.0, [5, 5, 10, 10]);
+
+        serial.done();
+    }
+
+    #[test]
+    fn cancel_ramp() {
+        let mut expectations = vec![
+            SerialTransaction::write(b';'),
+            SerialTransaction::write_many(b"VOL;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"VOL:40;"),
+        ];
+        expectations.extend(expect_volumes(&[39, 38]));
+
+        let mut serial = SerialMock::new(&expectations);
+        let mut up2stream_device = Up2Stream::new_combined(serial.clone());
+        let mut delay = Delay(Vec::new());
+        let mut checks = 0;
+
+        // Cancelled before the third step
+        let volume = up2stream_device
+            .ramp_volume(Volume(30), 100, &mut delay, || {
+                checks += 1;
+                checks == 3
+            })
+            .unwrap();
+        assert_eq!(volume, Volume(38));
+        assert_eq!(delay.0, [10, 10, 10]);
+
+        serial.done();
+    }
+
+    #[test]
+    fn poll_ramp() {
+        let mut expectations = vec![
+            SerialTransaction::write(b';'),
+            SerialTransaction::write_many(b"VOL;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"VOL:50;"),
+        ];
+        expectations.extend(expect_volumes(&[55, 60]));
+
+        let mut serial = SerialMock::new(&expectations);
+        let mut up2stream_device = Up2Stream::new_combined(serial.clone());
+        let time = Cell::new(1000);
+
+        let mut ramp =
+            VolumeRamp::new(&mut up2stream_device, Volume(60), 1000, || time.get()).unwrap();
+
+        // Nothing is sent until the volume changes
+        assert!(ramp.poll(&mut up2stream_device).is_err());
+        time.set(1050);
+        assert!(ramp.poll(&mut up2stream_device).is_err());
+        time.set(1500);
+        assert!(ramp.poll(&mut up2stream_device).is_err());
+        assert_eq!(ramp.volume(), Volume(55));
+
+        time.set(3000);
+        assert!(ramp.poll(&mut up2stream_device).is_ok());
+        assert!(ramp.poll(&mut up2stream_device).is_ok());
+
+        serial.done();
+    }
+
+    #[test]
+    fn cancel_fade() {
+        let mut up2stream_device = Up2StreamBuilder::new()
+            .build(Simulator::new(), Combined)
+            .unwrap();
+        let time = Cell::new(0);
+
+        let mut fade = VolumeRamp::fade_out(&mut up2stream_device, 300, || time.get()).unwrap();
+        assert_eq!(fade.start(), Volume(30));
+
+        time.set(100);
+        assert!(fade.poll(&mut up2stream_device).is_err());
+
+        let (volume, _) = fade.cancel();
+        assert_eq!(volume, Volume(20));
+        assert_eq!(up2stream_device.volume().unwrap(), Volume(20));
+
+        let mut fade =
+            VolumeRamp::fade_in(&mut up2stream_device, volume, 0, || time.get()).unwrap();
+        assert!(fade.poll(&mut up2stream_device).is_ok());
+        assert_eq!(up2stream_device.volume().unwrap(), Volume(20));
+    }
+}
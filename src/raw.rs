@@ -0,0 +1,121 @@
+//! Raw commands and queries for commands of the UART API that the driver does not wrap.
+
+use core::str::{FromStr, Split};
+
+use embedded_hal::serial::Read;
+
+use crate::command::encode_frame;
+use crate::error::Error;
+use crate::serial::Transmit;
+use crate::{Parameters, Up2Stream, PARAMETER_DELIMITER, PARAMETER_START, TERMINATOR};
+
+// Longest frame of a raw command
+const MAX_RAW_FRAME_LENGTH: usize = 64;
+
+/// The parameters of the response to a raw query, see [Up2Stream::raw_query].
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Response<'a>(&'a str);
+
+impl<'a> Response<'a> {
+    /// The parameters as received, e.g. `NET,0,30` for the response `STA:NET,0,30;`.
+    pub fn as_str(&self) -> &'a str {
+        self.0
+    }
+
+    /// Iterate over the parameters separated by `,`.
+    pub fn parameters(&self) -> Split<'a, char> {
+        self.0.split(PARAMETER_DELIMITER as char)
+    }
+
+    /// Parse the parameter at `index` into one of the parameter types, e.g. [Volume](crate::Volume).
+    pub fn parse<T>(&self, index: usize) -> Result<T, Error>
+    where
+        T: FromStr<Err = Error>,
+    {
+        self.parameters()
+            .nth(index)
+            .ok_or(Error::IllFormedReponse)?
+            .parse()
+    }
+}
+
+impl<R, W, const N: usize> Up2Stream<R, W, N>
+where
+    R: Read<u8>,
+    W: Transmit<R>,
+{
+    /// Send a command that is not otherwise supported by the driver, e.g. `raw_command("LED", &["1"])`
+    /// to send `LED:1;`. The parameters are separated by `,`.
+    ///
+    /// **This is an escape hatch** for commands added by newer firmware. The driver does not know
+    /// the meaning of the command, so the name and parameters are only checked to not break the framing:
+    /// the name must be alphanumeric and parameters must not be empty and consist of printable ASCII
+    /// characters other than `:`, `,` and `;`. Otherwise [Error::InvalidString] is returned without sending anything.
+    /// If the frame is longer than 64 bytes then [Error::BufferOverflow] is returned.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use up2stream_uart::Up2Stream;
+    /// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+    /// # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
+    /// # let mut up2stream_driver =Up2Stream::new_combined(uart);
+    /// up2stream_driver.raw_command("PST", &["3"]).unwrap();
+    /// ```
+    pub fn raw_command(&mut self, name: &str, parameters: &[&str]) -> Result<(), Error> {
+        check_name(name)?;
+        for parameter in parameters {
+            if parameter.is_empty() || !parameter.bytes().all(is_parameter_character) {
+                return Err(Error::InvalidString);
+            }
+        }
+
+        let mut frame = [0; MAX_RAW_FRAME_LENGTH];
+        let length = encode_frame(
+            name.as_bytes(),
+            parameters.iter().map(|parameter| parameter.as_bytes()),
+            &mut frame,
+        )?;
+
+        self.send_frame(&frame[..length])
+            .map_err(|_| Error::SendCommand)
+    }
+
+    /// Send a query that is not otherwise supported by the driver and return the parameters of the
+    /// response, e.g. `raw_query("LED")` to read if the LEDs are enabled.
+    ///
+    /// **This is an escape hatch** for queries added by newer firmware. The query uses the same
+    /// framing, noise handling, timeouts and retries as the other queries, but the parameters of
+    /// the response may contain any printable ASCII character or space except `;`, e.g. the name
+    /// of the board. The name must be alphanumeric, otherwise [Error::InvalidString] is returned
+    /// without sending anything.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use up2stream_uart::{Up2Stream, Volume};
+    /// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+    /// # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
+    /// # let mut up2stream_driver =Up2Stream::new_combined(uart);
+    /// let response = up2stream_driver.raw_query("STA").unwrap();
+    /// let volume: Volume = response.parse(2).unwrap();
+    /// ```
+    pub fn raw_query(&mut self, name: &str) -> Result<Response<'_>, Error> {
+        check_name(name)?;
+
+        self.send_query_with(name, Parameters::Printable)
+            .map(Response)
+    }
+}
+
+fn check_name(name: &str) -> Result<(), Error> {
+    if name.is_empty() || !name.bytes().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(Error::InvalidString);
+    }
+    Ok(())
+}
+
+fn is_parameter_character(c: u8) -> bool {
+    (c.is_ascii_graphic() || c == b' ')
+        && c != TERMINATOR
+        && c != PARAMETER_START
+        && c != PARAMETER_DELIMITER
+}
@@ -0,0 +1,507 @@
+//! Enforcing a declared state of the board, see [Reconciler].
+
+use arrayvec::ArrayVec;
+use embedded_hal::serial::Read;
+
+use crate::clock::Clock;
+use crate::command::Command;
+use crate::error::Error;
+use crate::serial::Transmit;
+use crate::{
+    Bass, DeviceStatus, Led, ScalarParameter, Source, Switch, Treble, Up2Stream, Volume,
+    DEFAULT_RESPONSE_SIZE,
+};
+
+/// The maximum number of corrections made by one reconciliation, one per field of [DesiredState].
+pub const MAX_CORRECTIONS: usize = 6;
+
+/// The corrections made by one reconciliation, in the order the commands were sent.
+pub type Corrections = ArrayVec<Correction, MAX_CORRECTIONS>;
+
+/// The state the board should be kept in. Fields set to `None` are not enforced.
+///
+/// The minimum volume must not be above the maximum volume, see [DesiredState::validate].
+///
+/// # Example
+/// ```
+/// use up2stream_uart::{DesiredState, Source, Volume};
+///
+/// let desired = DesiredState {
+///     source: Some(Source::LineIn),
+///     max_volume: Some(Volume::new(60).unwrap()),
+///     led: Some(false),
+///     ..DesiredState::default()
+/// };
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct DesiredState {
+    /// The input source
+    pub source: Option<Source>,
+    /// The lowest volume allowed
+    pub min_volume: Option<Volume>,
+    /// The highest volume allowed
+    pub max_volume: Option<Volume>,
+    /// If the audio is muted
+    pub mute: Option<bool>,
+    /// The setting for the bass filter
+    pub bass: Option<Bass>,
+    /// The setting for the treble filter
+    pub treble: Option<Treble>,
+    /// If the LEDs are enabled
+    pub led: Option<bool>,
+}
+
+/// The error returned by a reconciliation, with the corrections that were sent before it.
+#[derive(Debug)]
+pub struct ReconcileError {
+    pub applied: Corrections,
+    pub error: Error,
+}
+
+impl From<Error> for ReconcileError {
+    fn from(error: Error) -> Self {
+        ReconcileError {
+            applied: Corrections::new(),
+            error,
+        }
+    }
+}
+
+/// A change made to bring the board back to the [DesiredState], with the value found on the board
+/// and the value it was set to.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Correction {
+    Source { from: Source, to: Source },
+    Volume { from: Volume, to: Volume },
+    Mute { from: bool, to: bool },
+    Bass { from: Bass, to: Bass },
+    Treble { from: Treble, to: Treble },
+    Led { from: bool, to: bool },
+}
+
+impl Correction {
+    /// The command making the correction.
+    pub fn command(&self) -> Command {
+        match *self {
+            Self::Source { to, .. } => Command::SelectSource(to),
+            Self::Volume { to, .. } => Command::SetVolume(to),
+            Self::Mute { to, .. } => Command::SetMute(Switch::from(to)),
+            Self::Bass { to, .. } => Command::SetBass(to),
+            Self::Treble { to, .. } => Command::SetTreble(to),
+            Self::Led { to, .. } => Command::SetLed(Led::from(to)),
+        }
+    }
+}
+
+impl DesiredState {
+    /// Check that the desired state can be reached, returning [Error::InvalidDesiredState] if
+    /// the minimum volume is above the maximum volume.
+    pub fn validate(&self) -> Result<(), Error> {
+        match (self.min_volume, self.max_volume) {
+            (Some(min), Some(max)) if min.get() > max.get() => Err(Error::InvalidDesiredState),
+            _ => Ok(()),
+        }
+    }
+
+    /// The corrections needed to bring a board with the given `status` to the desired state, at
+    /// most one per field. A volume outside of the allowed range is set to the nearest limit.
+    pub fn corrections(&self, status: &DeviceStatus) -> Corrections {
+        let mut corrections = Corrections::new();
+
+        if let Some(source) = self.source.filter(|source| *source != status.source) {
+            corrections.push(Correction::Source {
+                from: status.source,
+                to: source,
+            });
+        }
+
+        let volume = status.volume.get();
+        let too_low = self.min_volume.filter(|min| volume < min.get());
+        let too_high = self.max_volume.filter(|max| volume > max.get());
+        if let Some(limit) = too_low.or(too_high) {
+            corrections.push(Correction::Volume {
+                from: status.volume,
+                to: limit,
+            });
+        }
+
+        if let Some(mute) = self.mute.filter(|mute| *mute != status.mute) {
+            corrections.push(Correction::Mute {
+                from: status.mute,
+                to: mute,
+            });
+        }
+
+        if let Some(bass) = self.bass.filter(|bass| *bass != status.bass) {
+            corrections.push(Correction::Bass {
+                from: status.bass,
+                to: bass,
+            });
+        }
+
+        if let Some(treble) = self.treble.filter(|treble| *treble != status.treble) {
+            corrections.push(Correction::Treble {
+                from: status.treble,
+                to: treble,
+            });
+        }
+
+        if let Some(led) = self.led.filter(|led| *led != status.led) {
+            corrections.push(Correction::Led {
+                from: status.led,
+                to: led,
+            });
+        }
+
+        corrections
+    }
+}
+
+/// Keeps the board in a [DesiredState], although it can be changed behind the back of the driver,
+/// e.g. by the Arylic app, the IR remote or the volume knob.
+///
+/// Each reconciliation compares the [status](Up2Stream::status) of the board with the desired
+/// state and sends only the commands needed to correct the fields that drifted.
+///
+/// # Example
+/// ```
+/// use up2stream_uart::{Correction, DesiredState, Reconciler, Up2Stream, Volume};
+/// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+/// # let mut serial = SerialMock::new(&[
+/// #     SerialTransaction::write(b';'),
+/// #     SerialTransaction::write_many(b"STA;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::read_many(b"STA:NET,0,75,0,0,1,1,1,1,0;"),
+/// #     SerialTransaction::write_many(b"VOL:60;"),
+/// #     SerialTransaction::flush(),
+/// # ]);
+/// # let mut timer = 0;
+/// let clock = || { timer += 100; timer };
+///
+/// let desired = DesiredState {
+///     max_volume: Some(Volume::new(60).unwrap()),
+///     ..DesiredState::default()
+/// };
+///
+/// // Check the state of the board every 2 s
+/// let mut reconciler =
+///     Reconciler::new(Up2Stream::new_combined(serial.clone()), clock, 2_000, desired).unwrap();
+///
+/// // Called from the main loop
+/// if let Some(corrections) = reconciler.poll().unwrap() {
+///     for correction in corrections {
+///         // The volume has been turned down from 75 to 60
+///         assert!(matches!(correction, Correction::Volume { .. }));
+///     }
+/// }
+/// # serial.done();
+/// ```
+pub struct Reconciler<R, W, C, const N: usize = DEFAULT_RESPONSE_SIZE>
+where
+    R: Read<u8>,
+    W: Transmit<R>,
+{
+    driver: Up2Stream<R, W, N>,
+    clock: C,
+    interval_ms: u32,
+    desired: DesiredState,
+    last_check_ms: Option<u32>,
+}
+
+impl<R, W, C, const N: usize> Reconciler<R, W, C, N>
+where
+    R: Read<u8>,
+    W: Transmit<R>,
+    C: Clock,
+{
+    /// Keep the board controlled by `driver` in the `desired` state, checking it at most every
+    /// `interval_ms` milliseconds according to `clock` when using [Reconciler::poll].
+    ///
+    /// Returns [Error::InvalidDesiredState] if the desired state is invalid, see [DesiredState::validate].
+    pub fn new(
+        driver: Up2Stream<R, W, N>,
+        clock: C,
+        interval_ms: u32,
+        desired: DesiredState,
+    ) -> Result<Self, Error> {
+        desired.validate()?;
+
+        Ok(Reconciler {
+            driver,
+            clock,
+            interval_ms,
+            desired,
+            last_check_ms: None,
+        })
+    }
+
+    /// The state the board is kept in.
+    pub fn desired(&self) -> &DesiredState {
+        &self.desired
+    }
+
+    /// Change the state the board is kept in. The board is checked at the next poll.
+    ///
+    /// Returns [Error::InvalidDesiredState], keeping the current desired state, if the new one is
+    /// invalid, see [DesiredState::validate].
+    pub fn set_desired(&mut self, desired: DesiredState) -> Result<(), Error> {
+        desired.validate()?;
+        self.desired = desired;
+        self.last_check_ms = None;
+        Ok(())
+    }
+
+    /// The driver, e.g. to control the board between reconciliations.
+    pub fn driver(&mut self) -> &mut Up2Stream<R, W, N> {
+        &mut self.driver
+    }
+
+    /// Give back the driver and the clock.
+    pub fn release(self) -> (Up2Stream<R, W, N>, C) {
+        (self.driver, self.clock)
+    }
+
+    /// Reconcile if the interval has elapsed since the last check, returning `None` otherwise.
+    /// This does not block and is meant to be called from the main loop.
+    pub fn poll(&mut self) -> Result<Option<Corrections>, ReconcileError> {
+        let now = self.clock.now_ms();
+        if matches!(self.last_check_ms, Some(last) if now.wrapping_sub(last) < self.interval_ms) {
+            return Ok(None);
+        }
+        self.last_check_ms = Some(now);
+
+        self.reconcile().map(Some)
+    }
+
+    /// Fetch the status of the board and send the commands correcting the fields that differ from
+    /// the desired state, returning the corrections made.
+    ///
+    /// If a command cannot be sent, the remaining commands are not sent and the error is returned
+    /// in a [ReconcileError] together with the corrections already made; the board is checked
+    /// again at the next reconciliation.
+    pub fn reconcile(&mut self) -> Result<Corrections, ReconcileError> {
+        let status = self.driver.status()?;
+        let corrections = self.desired.corrections(&status);
+        let mut applied = Corrections::new();
+
+        for correction in corrections {
+            if let Err(error) = self.driver.send(&correction.command()) {
+                return Err(ReconcileError { applied, error });
+            }
+            applied.push(correction);
+        }
+
+        Ok(applied)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::cell::Cell;
+
+    use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+    use embedded_hal_mock::MockError;
+
+    use crate::sim::Simulator;
+    use crate::{Combined, Up2StreamBuilder};
+
+    fn status() -> DeviceStatus {
+        "NET,1,30,-2,4,1,1,1,1,0".parse().unwrap()
+    }
+
+    #[test]
+    fn no_corrections_when_in_desired_state() {
+        let desired = DesiredState {
+            source: Some(Source::Net),
+            min_volume: Some(Volume::new(30).unwrap()),
+            max_volume: Some(Volume::new(30).unwrap()),
+            mute: Some(true),
+            bass: Some(Bass::new(4).unwrap()),
+            treble: Some(Treble::new(-2).unwrap()),
+            led: Some(true),
+        };
+
+        assert!(desired.corrections(&status()).is_empty());
+        assert!(DesiredState::default().corrections(&status()).is_empty());
+    }
+
+    #[test]
+    fn volume_set_to_nearest_limit() {
+        let mut desired = DesiredState {
+            min_volume: Some(Volume::new(40).unwrap()),
+            ..DesiredState::default()
+        };
+
+        assert_eq!(
+            desired.corrections(&status()).as_slice(),
+            [Correction::Volume {
+                from: Volume::new(30).unwrap(),
+                to: Volume::new(40).unwrap()
+            }]
+        );
+
+        desired.min_volume = None;
+        desired.max_volume = Some(Volume::new(20).unwrap());
+        assert_eq!(
+            desired.corrections(&status()).as_slice(),
+            [Correction::Volume {
+                from: Volume::new(30).unwrap(),
+                to: Volume::new(20).unwrap()
+            }]
+        );
+    }
+
+    #[test]
+    fn fewest_commands_sent() {
+        let expectations = [
+            SerialTransaction::write(b';'),
+            SerialTransaction::write_many(b"STA;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"STA:NET,0,75,-2,4,1,1,1,1,0;"),
+            SerialTransaction::write_many(b"SRC:LINE-IN;"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(b"VOL:60;"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(b"LED:0;"),
+            SerialTransaction::flush(),
+        ];
+
+        let mut serial = SerialMock::new(&expectations);
+
+        let desired = DesiredState {
+            source: Some(Source::LineIn),
+            max_volume: Some(Volume::new(60).unwrap()),
+            mute: Some(false),
+            led: Some(false),
+            ..DesiredState::default()
+        };
+
+        let mut reconciler =
+            Reconciler::new(Up2Stream::new_combined(serial.clone()), || 0, 0, desired).unwrap();
+
+        let corrections = reconciler.reconcile().unwrap();
+        assert_eq!(
+            corrections.as_slice(),
+            [
+                Correction::Source {
+                    from: Source::Net,
+                    to: Source::LineIn
+                },
+                Correction::Volume {
+                    from: Volume::new(75).unwrap(),
+                    to: Volume::new(60).unwrap()
+                },
+                Correction::Led {
+                    from: true,
+                    to: false
+                },
+            ]
+        );
+
+        serial.done();
+    }
+
+    #[test]
+    fn corrections_sent_before_error_returned() {
+        let expectations = [
+            SerialTransaction::write(b';'),
+            SerialTransaction::write_many(b"STA;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"STA:NET,0,75,-2,4,1,1,1,1,0;"),
+            SerialTransaction::write_many(b"SRC:LINE-IN;"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(b"VOL:60;"),
+            SerialTransaction::flush_error(nb::Error::Other(MockError::Io(
+                std::io::ErrorKind::Other,
+            ))),
+        ];
+
+        let mut serial = SerialMock::new(&expectations);
+
+        let desired = DesiredState {
+            source: Some(Source::LineIn),
+            max_volume: Some(Volume::new(60).unwrap()),
+            led: Some(false),
+            ..DesiredState::default()
+        };
+
+        let mut reconciler =
+            Reconciler::new(Up2Stream::new_combined(serial.clone()), || 0, 0, desired).unwrap();
+
+        let error = reconciler.reconcile().unwrap_err();
+        assert!(matches!(error.error, Error::SendCommand));
+        assert_eq!(
+            error.applied.as_slice(),
+            [Correction::Source {
+                from: Source::Net,
+                to: Source::LineIn
+            }]
+        );
+
+        serial.done();
+    }
+
+    #[test]
+    fn min_volume_above_max_volume_rejected() {
+        let invalid = DesiredState {
+            min_volume: Some(Volume::new(50).unwrap()),
+            max_volume: Some(Volume::new(40).unwrap()),
+            ..DesiredState::default()
+        };
+
+        let driver = Up2StreamBuilder::new()
+            .build(Simulator::new(), Combined)
+            .unwrap();
+        assert!(matches!(
+            Reconciler::new(driver, || 0, 0, invalid),
+            Err(Error::InvalidDesiredState)
+        ));
+
+        let driver = Up2StreamBuilder::new()
+            .build(Simulator::new(), Combined)
+            .unwrap();
+        let mut reconciler = Reconciler::new(driver, || 0, 0, DesiredState::default()).unwrap();
+        assert!(matches!(
+            reconciler.set_desired(invalid),
+            Err(Error::InvalidDesiredState)
+        ));
+        assert_eq!(reconciler.desired(), &DesiredState::default());
+    }
+
+    #[test]
+    fn poll_reconciles_after_interval() {
+        let mut simulator = Simulator::new();
+        simulator.state_mut().volume = Volume::new(80).unwrap();
+
+        let driver = Up2StreamBuilder::new().build(simulator, Combined).unwrap();
+        let time = Cell::new(0);
+        let desired = DesiredState {
+            max_volume: Some(Volume::new(60).unwrap()),
+            ..DesiredState::default()
+        };
+        let mut reconciler = Reconciler::new(driver, || time.get(), 1000, desired).unwrap();
+
+        assert_eq!(reconciler.poll().unwrap().unwrap().len(), 1);
+        assert_eq!(reconciler.driver().volume().unwrap().get(), 60);
+
+        // The volume knob is turned up
+        reconciler
+            .driver()
+            .set_volume(Volume::new(90).unwrap())
+            .unwrap();
+
+        time.set(999);
+        assert_eq!(reconciler.poll().unwrap(), None);
+        time.set(1000);
+        assert_eq!(reconciler.poll().unwrap().unwrap().len(), 1);
+
+        time.set(1001);
+        reconciler.set_desired(DesiredState::default()).unwrap();
+        assert!(reconciler.poll().unwrap().unwrap().is_empty());
+
+        let (driver, _) = reconciler.release();
+        let (simulator, _) = driver.release();
+        assert_eq!(simulator.state().volume.get(), 60);
+    }
+}
@@ -0,0 +1,218 @@
+//! Relative changes of the volume, bass and treble, e.g. [Up2Stream::volume_up].
+//!
+//! The [Arylic UART API](https://developer.arylic.com/download/api-info-4.xlsx) only defines
+//! `VOL`, `BAS` and `TRE` with absolute values, so the current value is read and the new value is
+//! set. With a [Cached] driver the current value usually comes from the shadow copy, so that a
+//! single command is sent.
+
+use embedded_hal::serial::Read;
+
+use crate::cache::Cached;
+use crate::clock::Clock;
+use crate::error::Error;
+use crate::serial::Transmit;
+use crate::{Bass, ScalarParameter, Treble, Up2Stream, Volume};
+
+// The change for `steps`, large step counts saturate anyway as all ranges are narrower than `i8::MAX`
+fn step_delta(steps: u8) -> i8 {
+    i8::try_from(steps).unwrap_or(i8::MAX)
+}
+
+// Change the value read with `get` by `delta` steps, stopping at its limits, and set it with `set`
+// unless it is unchanged
+fn change<D, T>(
+    device: &mut D,
+    delta: i8,
+    get: impl FnOnce(&mut D) -> Result<T, Error>,
+    set: impl FnOnce(&mut D, T) -> Result<(), Error>,
+) -> Result<T, Error>
+where
+    T: ScalarParameter + PartialEq + Copy,
+{
+    let value = get(device)?;
+    let changed = value.saturating_add(delta);
+    if changed != value {
+        set(device, changed)?;
+    }
+    Ok(changed)
+}
+
+impl<R, W, const N: usize> Up2Stream<R, W, N>
+where
+    R: Read<u8>,
+    W: Transmit<R>,
+{
+    /// Turn the volume up by `steps`, stopping at the maximum volume, and return the new volume.
+    /// Nothing is sent if the volume is already at the maximum.
+    ///
+    /// # Example
+    /// ```
+    /// use up2stream_uart::{ScalarParameter, Up2Stream};
+    /// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+    /// # let mut uart = SerialMock::new(&[
+    /// #     SerialTransaction::write(b';'),
+    /// #     SerialTransaction::write_many(b"VOL;"),
+    /// #     SerialTransaction::flush(),
+    /// #     SerialTransaction::read_many(b"VOL:40;"),
+    /// #     SerialTransaction::write_many(b"VOL:45;"),
+    /// #     SerialTransaction::flush(),
+    /// # ]);
+    /// # let mut up2stream_driver = Up2Stream::new_combined(uart.clone());
+    /// let volume = up2stream_driver.volume_up(5).unwrap();
+    ///
+    /// assert_eq!(volume.get(), 45);
+    /// # uart.done();
+    /// ```
+    pub fn volume_up(&mut self, steps: u8) -> Result<Volume, Error> {
+        change(self, step_delta(steps), Self::volume, Self::set_volume)
+    }
+
+    /// Turn the volume down by `steps`, stopping at 0, and return the new volume.
+    /// Nothing is sent if the volume is already 0.
+    pub fn volume_down(&mut self, steps: u8) -> Result<Volume, Error> {
+        change(self, -step_delta(steps), Self::volume, Self::set_volume)
+    }
+
+    /// Raise the bass by `steps`, stopping at the maximum, and return the new bass.
+    pub fn bass_up(&mut self, steps: u8) -> Result<Bass, Error> {
+        change(self, step_delta(steps), Self::bass, Self::set_bass)
+    }
+
+    /// Lower the bass by `steps`, stopping at the minimum, and return the new bass.
+    pub fn bass_down(&mut self, steps: u8) -> Result<Bass, Error> {
+        change(self, -step_delta(steps), Self::bass, Self::set_bass)
+    }
+
+    /// Raise the treble by `steps`, stopping at the maximum, and return the new treble.
+    pub fn treble_up(&mut self, steps: u8) -> Result<Treble, Error> {
+        change(self, step_delta(steps), Self::treble, Self::set_treble)
+    }
+
+    /// Lower the treble by `steps`, stopping at the minimum, and return the new treble.
+    pub fn treble_down(&mut self, steps: u8) -> Result<Treble, Error> {
+        change(self, -step_delta(steps), Self::treble, Self::set_treble)
+    }
+}
+
+impl<R, W, C, const N: usize> Cached<R, W, C, N>
+where
+    R: Read<u8>,
+    W: Transmit<R>,
+    C: Clock,
+{
+    /// Turn the volume up by `steps`, see [Up2Stream::volume_up]. The current volume is taken from
+    /// the shadow copy if it is fresh.
+    pub fn volume_up(&mut self, steps: u8) -> Result<Volume, Error> {
+        change(self, step_delta(steps), Self::volume, Self::set_volume)
+    }
+
+    /// Turn the volume down by `steps`, see [Up2Stream::volume_down].
+    pub fn volume_down(&mut self, steps: u8) -> Result<Volume, Error> {
+        change(self, -step_delta(steps), Self::volume, Self::set_volume)
+    }
+
+    /// Raise the bass by `steps`, see [Up2Stream::bass_up].
+    pub fn bass_up(&mut self, steps: u8) -> Result<Bass, Error> {
+        change(self, step_delta(steps), Self::bass, Self::set_bass)
+    }
+
+    /// Lower the bass by `steps`, see [Up2Stream::bass_down].
+    pub fn bass_down(&mut self, steps: u8) -> Result<Bass, Error> {
+        change(self, -step_delta(steps), Self::bass, Self::set_bass)
+    }
+
+    /// Raise the treble by `steps`, see [Up2Stream::treble_up].
+    pub fn treble_up(&mut self, steps: u8) -> Result<Treble, Error> {
+        change(self, step_delta(steps), Self::treble, Self::set_treble)
+    }
+
+    /// Lower the treble by `steps`, see [Up2Stream::treble_down].
+    pub fn treble_down(&mut self, steps: u8) -> Result<Treble, Error> {
+        change(self, -step_delta(steps), Self::treble, Self::set_treble)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+
+    use crate::sim::Simulator;
+    use crate::{Combined, Up2StreamBuilder};
+
+    #[test]
+    fn volume_up_down() {
+        let expectations = [
+            SerialTransaction::write(b';'),
+            SerialTransaction::write_many(b"VOL;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"VOL:98;"),
+            SerialTransaction::write_many(b"VOL:100;"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(b"VOL;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"VOL:100;"),
+            SerialTransaction::write_many(b"VOL;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"VOL:100;"),
+            SerialTransaction::write_many(b"VOL:99;"),
+            SerialTransaction::flush(),
+        ];
+
+        let mut serial = SerialMock::new(&expectations);
+        let mut up2stream_device = Up2Stream::new_combined(serial.clone());
+
+        assert_eq!(up2stream_device.volume_up(5).unwrap(), Volume(100));
+        // Nothing is sent at the limit
+        assert_eq!(up2stream_device.volume_up(1).unwrap(), Volume(100));
+        assert_eq!(up2stream_device.volume_down(1).unwrap(), Volume(99));
+
+        serial.done();
+    }
+
+    #[test]
+    fn bass_treble() {
+        let mut up2stream_device = Up2StreamBuilder::new()
+            .build(Simulator::new(), Combined)
+            .unwrap();
+
+        assert_eq!(up2stream_device.bass_down(20).unwrap(), Bass(-10));
+        assert_eq!(up2stream_device.bass_up(3).unwrap(), Bass(-7));
+        assert_eq!(up2stream_device.treble_up(12).unwrap(), Treble(10));
+        assert_eq!(up2stream_device.treble_down(1).unwrap(), Treble(9));
+
+        let (simulator, _) = up2stream_device.release();
+        assert_eq!(simulator.state().bass, Bass(-7));
+        assert_eq!(simulator.state().treble, Treble(9));
+    }
+
+    #[test]
+    fn cached_single_command() {
+        let expectations = [
+            SerialTransaction::write(b';'),
+            SerialTransaction::write_many(b"STA;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"STA:NET,0,30,-2,4,1,1,1,1,0;"),
+            SerialTransaction::write_many(b"VOL:28;"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(b"VOL:27;"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(b"BAS:5;"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(b"TRE:-10;"),
+            SerialTransaction::flush(),
+        ];
+
+        let mut serial = SerialMock::new(&expectations);
+        let mut up2stream_device = Cached::new(Up2Stream::new_combined(serial.clone()), || 0, 1000);
+
+        up2stream_device.status().unwrap();
+        assert_eq!(up2stream_device.volume_down(2).unwrap(), Volume(28));
+        assert_eq!(up2stream_device.volume_down(1).unwrap(), Volume(27));
+        assert_eq!(up2stream_device.bass_up(1).unwrap(), Bass(5));
+        assert_eq!(up2stream_device.treble_down(10).unwrap(), Treble(-10));
+
+        serial.done();
+    }
+}
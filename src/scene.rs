@@ -0,0 +1,288 @@
+//! Snapshots of the user-visible configuration of the board, see [Scene].
+
+use arrayvec::ArrayVec;
+use embedded_hal::serial::Read;
+
+use crate::command::Command;
+use crate::error::Error;
+use crate::serial::Transmit;
+use crate::{AudioChannel, Bass, Led, LoopMode, Source, Switch, Treble, Up2Stream, Volume};
+
+// One command per field of a scene
+const MAX_SCENE_COMMANDS: usize = 10;
+
+/// The complete user-visible configuration of the board, taken with [Up2Stream::snapshot] and
+/// restored with [Up2Stream::apply].
+///
+/// # Example
+/// ```
+/// use up2stream_uart::{Scene, Up2Stream, Volume};
+/// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+/// # let mut serial = SerialMock::new(&[
+/// #     SerialTransaction::write(b';'),
+/// #     SerialTransaction::write_many(b"STA;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::read_many(b"STA:LINE-IN,0,60,0,0,1,1,1,1,0;"),
+/// #     SerialTransaction::write_many(b"BEP;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::read_many(b"BEP:1;"),
+/// #     SerialTransaction::write_many(b"VBS;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::read_many(b"VBS:0;"),
+/// #     SerialTransaction::write_many(b"LPM;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::read_many(b"LPM:SEQUENCE;"),
+/// #     SerialTransaction::write_many(b"CHN;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::read_many(b"CHN:S;"),
+/// #     SerialTransaction::write_many(b"STA;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::read_many(b"STA:LINE-IN,0,60,0,0,1,1,1,1,0;"),
+/// #     SerialTransaction::write_many(b"BEP;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::read_many(b"BEP:1;"),
+/// #     SerialTransaction::write_many(b"VBS;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::read_many(b"VBS:0;"),
+/// #     SerialTransaction::write_many(b"LPM;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::read_many(b"LPM:SEQUENCE;"),
+/// #     SerialTransaction::write_many(b"CHN;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::read_many(b"CHN:S;"),
+/// #     SerialTransaction::write_many(b"VOL:20;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::write_many(b"LED:0;"),
+/// #     SerialTransaction::flush(),
+/// # ]);
+/// # let mut up2stream_device = Up2Stream::new_combined(serial.clone());
+/// let movie = up2stream_device.snapshot().unwrap();
+///
+/// let night = Scene {
+///     volume: Volume::new(20).unwrap(),
+///     led: false,
+///     ..movie
+/// };
+///
+/// // Only sends VOL:20; and LED:0;
+/// up2stream_device.apply(&night).unwrap();
+/// # serial.done();
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Scene {
+    /// The input source
+    pub source: Source,
+    /// The volume
+    pub volume: Volume,
+    /// The setting for the bass filter
+    pub bass: Bass,
+    /// The setting for the treble filter
+    pub treble: Treble,
+    /// If the audio is muted
+    pub mute: bool,
+    /// If the LEDs are enabled
+    pub led: bool,
+    /// If the beep sound is enabled
+    pub beep: bool,
+    /// If virtual bass is enabled
+    pub virtual_bass: bool,
+    /// The loop mode for network playback
+    pub loop_mode: LoopMode,
+    /// The audio channel that is output
+    pub channel: AudioChannel,
+}
+
+impl Scene {
+    // The commands changing a board configured as `current` into this scene, in the order of the fields
+    fn commands(&self, current: &Scene) -> ArrayVec<Command, MAX_SCENE_COMMANDS> {
+        let changes = [
+            (self.source != current.source).then_some(Command::SelectSource(self.source)),
+            (self.volume != current.volume).then_some(Command::SetVolume(self.volume)),
+            (self.bass != current.bass).then_some(Command::SetBass(self.bass)),
+            (self.treble != current.treble).then_some(Command::SetTreble(self.treble)),
+            (self.mute != current.mute).then_some(Command::SetMute(Switch::from(self.mute))),
+            (self.led != current.led).then_some(Command::SetLed(Led::from(self.led))),
+            (self.beep != current.beep).then_some(Command::SetBeep(self.beep)),
+            (self.virtual_bass != current.virtual_bass)
+                .then_some(Command::SetVirtualBass(Switch::from(self.virtual_bass))),
+            (self.loop_mode != current.loop_mode).then_some(Command::SetLoopMode(self.loop_mode)),
+            (self.channel != current.channel).then_some(Command::SetAudioChannel(self.channel)),
+        ];
+
+        changes.into_iter().flatten().collect()
+    }
+}
+
+impl<R, W, const N: usize> Up2Stream<R, W, N>
+where
+    R: Read<u8>,
+    W: Transmit<R>,
+{
+    /// Take a snapshot of the user-visible configuration of the board, see [Scene].
+    pub fn snapshot(&mut self) -> Result<Scene, Error> {
+        let status = self.status()?;
+
+        Ok(Scene {
+            source: status.source,
+            volume: status.volume,
+            bass: status.bass,
+            treble: status.treble,
+            mute: status.mute,
+            led: status.led,
+            beep: self.beep()?,
+            virtual_bass: self.virtual_bass()?,
+            loop_mode: self.loop_mode()?,
+            channel: self.audio_channel()?,
+        })
+    }
+
+    /// Restore the configuration of the board to the `scene`.
+    ///
+    /// The current configuration is read first, and only the fields that differ are sent. The source
+    /// is selected first and the other fields follow in the order of [Scene]. If a command fails, its
+    /// error is returned without sending the remaining fields.
+    pub fn apply(&mut self, scene: &Scene) -> Result<(), Error> {
+        let current = self.snapshot()?;
+
+        for command in scene.commands(&current) {
+            self.send(&command)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+    use embedded_hal_mock::MockError;
+
+    use crate::sim::Simulator;
+    use crate::{Combined, Up2StreamBuilder};
+
+    fn expect_snapshot() -> Vec<SerialTransaction<u8>> {
+        let mut expectations = Vec::new();
+        for (query, response) in [
+            (&b"STA;"[..], &b"STA:NET,0,30,-2,4,1,1,1,1,0;"[..]),
+            (b"BEP;", b"BEP:1;"),
+            (b"VBS;", b"VBS:0;"),
+            (b"LPM;", b"LPM:REPEATALL;"),
+            (b"CHN;", b"CHN:S;"),
+        ] {
+            expectations.push(SerialTransaction::write_many(query));
+            expectations.push(SerialTransaction::flush());
+            expectations.push(SerialTransaction::read_many(response));
+        }
+        expectations
+    }
+
+    fn scene() -> Scene {
+        Scene {
+            source: Source::Net,
+            volume: Volume::new(30).unwrap(),
+            bass: Bass::new(4).unwrap(),
+            treble: Treble::new(-2).unwrap(),
+            mute: false,
+            led: true,
+            beep: true,
+            virtual_bass: false,
+            loop_mode: LoopMode::RepeatAll,
+            channel: AudioChannel::Stereo,
+        }
+    }
+
+    #[test]
+    fn snapshot() {
+        let mut expectations = vec![SerialTransaction::write(b';')];
+        expectations.extend(expect_snapshot());
+
+        let mut serial = SerialMock::new(&expectations);
+        let mut up2stream_device = Up2Stream::new_combined(serial.clone());
+
+        assert_eq!(up2stream_device.snapshot().unwrap(), scene());
+
+        serial.done();
+    }
+
+    #[test]
+    fn apply_sends_only_differences() {
+        let mut expectations = vec![SerialTransaction::write(b';')];
+        expectations.extend(expect_snapshot());
+        expectations.extend([
+            SerialTransaction::write_many(b"SRC:LINE-IN;"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(b"MUT:1;"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(b"VBS:1;"),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend(expect_snapshot());
+
+        let mut serial = SerialMock::new(&expectations);
+        let mut up2stream_device = Up2Stream::new_combined(serial.clone());
+
+        let party = Scene {
+            source: Source::LineIn,
+            mute: true,
+            virtual_bass: true,
+            ..scene()
+        };
+        up2stream_device.apply(&party).unwrap();
+
+        // Nothing is sent if the board is already configured as the scene
+        up2stream_device.apply(&scene()).unwrap();
+
+        serial.done();
+    }
+
+    #[test]
+    fn apply_stops_at_first_failure() {
+        let mut expectations = vec![SerialTransaction::write(b';')];
+        expectations.extend(expect_snapshot());
+        expectations.extend([
+            SerialTransaction::write_many(b"VOL:10;"),
+            SerialTransaction::flush_error(nb::Error::Other(MockError::Io(
+                std::io::ErrorKind::Other,
+            ))),
+        ]);
+
+        let mut serial = SerialMock::new(&expectations);
+        let mut up2stream_device = Up2Stream::new_combined(serial.clone());
+
+        let night = Scene {
+            volume: Volume::new(10).unwrap(),
+            led: false,
+            ..scene()
+        };
+        assert!(matches!(
+            up2stream_device.apply(&night),
+            Err(Error::SendCommand)
+        ));
+
+        serial.done();
+    }
+
+    #[test]
+    fn restore_snapshot() {
+        let mut driver = Up2StreamBuilder::new()
+            .build(Simulator::new(), Combined)
+            .unwrap();
+
+        let saved = driver.snapshot().unwrap();
+
+        driver.select_input_source(Source::Bluetooth).unwrap();
+        driver.set_volume(Volume::new(80).unwrap()).unwrap();
+        driver.set_bass(Bass::new(-5).unwrap()).unwrap();
+        driver.set_led(Led::Off).unwrap();
+        driver.set_beep(false).unwrap();
+        driver.enable_virtual_bass().unwrap();
+        driver.set_loop_mode(LoopMode::Shuffle).unwrap();
+        driver.set_audio_channel(AudioChannel::Right).unwrap();
+        assert_ne!(driver.snapshot().unwrap(), saved);
+
+        driver.apply(&saved).unwrap();
+        assert_eq!(driver.snapshot().unwrap(), saved);
+    }
+}
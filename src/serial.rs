@@ -0,0 +1,96 @@
+//! Transmit side of the serial connection to the board.
+//!
+//! Most HALs split a UART peripheral into a receiving `Reader` half and a transmitting `Writer` half.
+//! These are passed separately to [Up2Stream::new](crate::Up2Stream::new). If a single peripheral
+//! implements both `Read` and `Write` then [Combined] is used as the writer and the data is
+//! transmitted through the reader.
+//!
+//! Frames are always written in full, waiting whenever the transmitter is not ready instead of treating
+//! this as an error. If the HAL provides an implementation of `embedded_hal::blocking::serial::Write`
+//! (for instance using DMA) then the writer can be wrapped in [Blocking] so that whole frames are written
+//! with `bwrite_all` and `bflush`.
+
+use embedded_hal::blocking;
+use embedded_hal::serial::Write;
+use nb::block;
+
+use crate::error::Error;
+
+/// Used as the writer of the driver if a single peripheral implements both the
+/// `embedded_hal::serial::{Read, Write}` traits.
+///
+/// See [Up2Stream::new_combined](crate::Up2Stream::new_combined).
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Combined;
+
+/// Wraps a writer implementing `embedded_hal::blocking::serial::Write<u8>` so that the driver
+/// transmits frames using `bwrite_all` and `bflush`.
+///
+/// # Example
+/// ```no_run
+/// use up2stream_uart::{Up2Stream, Blocking};
+/// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+/// # let mut uart =   SerialMock::new(&[SerialTransaction::read(b';')]);
+/// # let (reader, writer) = (uart.clone(), uart);
+/// let mut up2stream_driver = Up2Stream::new(reader, Blocking(writer));
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Blocking<W>(pub W);
+
+/// The transmitting half of the serial connection.
+///
+/// This is implemented for all types implementing `embedded_hal::serial::Write<u8>`, for
+/// [Blocking] and for [Combined], where the reader `R` is also used for transmitting.
+pub trait Transmit<R> {
+    /// Write all the bytes, waiting until each has been accepted by the peripheral.
+    fn write_all(&mut self, reader: &mut R, bytes: &[u8]) -> Result<(), Error>;
+
+    /// Wait until all written bytes have been transmitted.
+    fn flush(&mut self, reader: &mut R) -> Result<(), Error>;
+}
+
+impl<R, W> Transmit<R> for W
+where
+    W: Write<u8>,
+{
+    fn write_all(&mut self, _reader: &mut R, bytes: &[u8]) -> Result<(), Error> {
+        write_all(self, bytes)
+    }
+
+    fn flush(&mut self, _reader: &mut R) -> Result<(), Error> {
+        block!(Write::flush(self)).map_err(|_| Error::Write)
+    }
+}
+
+impl<R, W> Transmit<R> for Blocking<W>
+where
+    W: blocking::serial::Write<u8>,
+{
+    fn write_all(&mut self, _reader: &mut R, bytes: &[u8]) -> Result<(), Error> {
+        self.0.bwrite_all(bytes).map_err(|_| Error::Write)
+    }
+
+    fn flush(&mut self, _reader: &mut R) -> Result<(), Error> {
+        self.0.bflush().map_err(|_| Error::Write)
+    }
+}
+
+impl<UART> Transmit<UART> for Combined
+where
+    UART: Write<u8>,
+{
+    fn write_all(&mut self, reader: &mut UART, bytes: &[u8]) -> Result<(), Error> {
+        write_all(reader, bytes)
+    }
+
+    fn flush(&mut self, reader: &mut UART) -> Result<(), Error> {
+        block!(reader.flush()).map_err(|_| Error::Write)
+    }
+}
+
+fn write_all<W: Write<u8>>(writer: &mut W, bytes: &[u8]) -> Result<(), Error> {
+    for byte in bytes {
+        block!(writer.write(*byte)).map_err(|_| Error::Write)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,367 @@
+//! Persisting the settings of the board to flash, see [SettingsStore].
+//!
+//! The settings are stored as a [Scene] in a compact binary record of [ENCODED_SETTINGS_LENGTH]
+//! bytes (little endian):
+//!
+//! | Bytes | Content                                                     |
+//! |-------|-------------------------------------------------------------|
+//! | 0-1   | Magic `U2`                                                  |
+//! | 2     | Version of the encoding, [SETTINGS_VERSION]                 |
+//! | 3     | Source                                                      |
+//! | 4     | Volume                                                      |
+//! | 5     | Bass                                                        |
+//! | 6     | Treble                                                      |
+//! | 7     | Flags: bit 0 mute, bit 1 LED, bit 2 beep, bit 3 virtual bass |
+//! | 8     | Loop mode                                                   |
+//! | 9     | Audio channel                                               |
+//! | 10-13 | CRC-32 of bytes 0-9                                         |
+
+use embedded_hal::serial::Read;
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::error::Error;
+use crate::serial::Transmit;
+use crate::{
+    AudioChannel, Bass, LoopMode, ScalarParameter, Scene, Source, Treble, Up2Stream, Volume,
+};
+
+/// The version of the binary encoding of the settings.
+pub const SETTINGS_VERSION: u8 = 1;
+
+/// The length in bytes of the encoded settings.
+pub const ENCODED_SETTINGS_LENGTH: usize = 14;
+
+const MAGIC: [u8; 2] = *b"U2";
+const CRC_OFFSET: usize = ENCODED_SETTINGS_LENGTH - 4;
+
+// Largest write size of a flash supported by the store, e.g. the page size of the RP2040
+const MAX_WRITE_SIZE: usize = 256;
+
+const MUTE: u8 = 1 << 0;
+const LED: u8 = 1 << 1;
+const BEEP: u8 = 1 << 2;
+const VIRTUAL_BASS: u8 = 1 << 3;
+
+pub(crate) const SOURCES: [Source; 5] = [
+    Source::Net,
+    Source::Usb,
+    Source::Bluetooth,
+    Source::LineIn,
+    Source::Coax,
+];
+const LOOP_MODES: [LoopMode; 5] = [
+    LoopMode::RepeatAll,
+    LoopMode::RepeatOne,
+    LoopMode::RepeatShuffle,
+    LoopMode::Shuffle,
+    LoopMode::Sequence,
+];
+const CHANNELS: [AudioChannel; 3] = [
+    AudioChannel::Left,
+    AudioChannel::Right,
+    AudioChannel::Stereo,
+];
+
+impl Scene {
+    /// Encode the scene into the binary record stored in flash.
+    pub fn encode(&self) -> [u8; ENCODED_SETTINGS_LENGTH] {
+        let flag = |set: bool, flag: u8| if set { flag } else { 0 };
+
+        let mut record = [0; ENCODED_SETTINGS_LENGTH];
+        record[..2].copy_from_slice(&MAGIC);
+        record[2] = SETTINGS_VERSION;
+        record[3] = code(&SOURCES, &self.source);
+        record[4] = self.volume.get() as u8;
+        record[5] = self.bass.get() as u8;
+        record[6] = self.treble.get() as u8;
+        record[7] = flag(self.mute, MUTE)
+            | flag(self.led, LED)
+            | flag(self.beep, BEEP)
+            | flag(self.virtual_bass, VIRTUAL_BASS);
+        record[8] = code(&LOOP_MODES, &self.loop_mode);
+        record[9] = code(&CHANNELS, &self.channel);
+
+        let crc = crc32(&record[..CRC_OFFSET]);
+        record[CRC_OFFSET..].copy_from_slice(&crc.to_le_bytes());
+
+        record
+    }
+
+    /// Decode a binary record encoded with [Scene::encode].
+    ///
+    /// Returns [Error::InvalidSettings] if the record is too short, has a different version or
+    /// its CRC or one of its values is invalid.
+    pub fn decode(record: &[u8]) -> Result<Scene, Error> {
+        let record = record
+            .get(..ENCODED_SETTINGS_LENGTH)
+            .ok_or(Error::InvalidSettings)?;
+
+        let crc = u32::from_le_bytes([record[10], record[11], record[12], record[13]]);
+        if record[..2] != MAGIC
+            || record[2] != SETTINGS_VERSION
+            || crc32(&record[..CRC_OFFSET]) != crc
+        {
+            return Err(Error::InvalidSettings);
+        }
+
+        let invalid = |_| Error::InvalidSettings;
+
+        Ok(Scene {
+            source: value(&SOURCES, record[3])?,
+            volume: Volume::new(record[4] as i8).map_err(invalid)?,
+            bass: Bass::new(record[5] as i8).map_err(invalid)?,
+            treble: Treble::new(record[6] as i8).map_err(invalid)?,
+            mute: record[7] & MUTE != 0,
+            led: record[7] & LED != 0,
+            beep: record[7] & BEEP != 0,
+            virtual_bass: record[7] & VIRTUAL_BASS != 0,
+            loop_mode: value(&LOOP_MODES, record[8])?,
+            channel: value(&CHANNELS, record[9])?,
+        })
+    }
+}
+
+// The code of a value is its index in the table of all values
+pub(crate) fn code<T: PartialEq>(values: &[T], value: &T) -> u8 {
+    values.iter().position(|v| v == value).unwrap_or_default() as u8
+}
+
+pub(crate) fn value<T: Copy>(values: &[T], code: u8) -> Result<T, Error> {
+    values
+        .get(code as usize)
+        .copied()
+        .ok_or(Error::InvalidSettings)
+}
+
+// CRC-32 (IEEE 802.3), computed bitwise to avoid a lookup table
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Saves and loads the settings of the board in a sector of NOR flash, using the
+/// `embedded_storage::nor_flash` traits.
+///
+/// The settings are saved with [Up2Stream::save_settings] and re-applied with
+/// [Up2Stream::restore_after_boot], e.g. after a power cut or [SystemControl::Reset](crate::SystemControl::Reset).
+///
+/// # Example
+/// ```
+/// use embedded_storage::nor_flash::NorFlash;
+/// use up2stream_uart::{Combined, Error, SettingsStore, Up2Stream};
+/// # use embedded_hal_mock::serial::Mock as SerialMock;
+///
+/// fn start<F: NorFlash>(up2stream_device: &mut Up2Stream<SerialMock<u8>, Combined>, flash: F) -> Result<(), Error> {
+///     // Use the first sector of the flash
+///     let mut store = SettingsStore::new(flash, 0);
+///
+///     // Re-apply the saved settings, or save the current ones on the first boot
+///     if !up2stream_device.restore_after_boot(&mut store)? {
+///         up2stream_device.save_settings(&mut store)?;
+///     }
+///     Ok(())
+/// }
+/// ```
+pub struct SettingsStore<F> {
+    flash: F,
+    offset: u32,
+}
+
+impl<F: NorFlash> SettingsStore<F> {
+    /// Store the settings in the erase sector of `flash` starting at `offset`, which must be a
+    /// multiple of the erase size. The rest of the sector is not used.
+    pub fn new(flash: F, offset: u32) -> Self {
+        SettingsStore { flash, offset }
+    }
+
+    /// Give back the flash.
+    pub fn release(self) -> F {
+        self.flash
+    }
+
+    /// Load the saved settings, or `None` if the sector is erased.
+    ///
+    /// Returns [Error::InvalidSettings] if the saved settings are corrupted or have been saved
+    /// with another version of the encoding, and [Error::Storage] if the flash cannot be read.
+    pub fn load(&mut self) -> Result<Option<Scene>, Error> {
+        let mut buffer = [0; MAX_WRITE_SIZE];
+        let length = round_up(ENCODED_SETTINGS_LENGTH, F::READ_SIZE)?;
+
+        self.flash
+            .read(self.offset, &mut buffer[..length])
+            .map_err(|_| Error::Storage)?;
+
+        let record = &buffer[..ENCODED_SETTINGS_LENGTH];
+        if record.iter().all(|byte| *byte == 0xFF) {
+            return Ok(None);
+        }
+
+        Scene::decode(record).map(Some)
+    }
+
+    /// Save the settings, erasing the sector first. Nothing is written if the same settings are
+    /// already saved, to spare the flash.
+    ///
+    /// Returns [Error::Storage] if the flash cannot be erased or written.
+    pub fn save(&mut self, scene: &Scene) -> Result<(), Error> {
+        if matches!(self.load(), Ok(Some(saved)) if saved == *scene) {
+            return Ok(());
+        }
+
+        let mut buffer = [0xFF; MAX_WRITE_SIZE];
+        let length = round_up(ENCODED_SETTINGS_LENGTH, F::WRITE_SIZE)?;
+        buffer[..ENCODED_SETTINGS_LENGTH].copy_from_slice(&scene.encode());
+
+        self.erase()?;
+        self.flash
+            .write(self.offset, &buffer[..length])
+            .map_err(|_| Error::Storage)
+    }
+
+    /// Erase the saved settings.
+    pub fn erase(&mut self) -> Result<(), Error> {
+        self.flash
+            .erase(self.offset, self.offset + F::ERASE_SIZE as u32)
+            .map_err(|_| Error::Storage)
+    }
+}
+
+// Round the length up to a multiple of the read or write size of the flash
+fn round_up(length: usize, size: usize) -> Result<usize, Error> {
+    let rounded = length.div_ceil(size.max(1)) * size.max(1);
+    if rounded > MAX_WRITE_SIZE {
+        return Err(Error::Storage);
+    }
+    Ok(rounded)
+}
+
+impl<R, W, const N: usize> Up2Stream<R, W, N>
+where
+    R: Read<u8>,
+    W: Transmit<R>,
+{
+    /// Save the current settings of the board to flash, see [Up2Stream::snapshot].
+    pub fn save_settings<F: NorFlash>(
+        &mut self,
+        store: &mut SettingsStore<F>,
+    ) -> Result<(), Error> {
+        let scene = self.snapshot()?;
+
+        store.save(&scene)
+    }
+
+    /// Re-apply the settings saved with [Up2Stream::save_settings], e.g. after the board has been
+    /// powered up. Only the settings that differ from the current ones are sent, see [Up2Stream::apply].
+    ///
+    /// Returns `false` without communicating with the board if no settings have been saved.
+    pub fn restore_after_boot<F: NorFlash>(
+        &mut self,
+        store: &mut SettingsStore<F>,
+    ) -> Result<bool, Error> {
+        match store.load()? {
+            Some(scene) => self.apply(&scene).map(|_| true),
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::sim::Simulator;
+    use crate::testing::MemoryFlash;
+    use crate::{Combined, Up2StreamBuilder};
+
+    fn scene() -> Scene {
+        Scene {
+            source: Source::LineIn,
+            volume: Volume::new(45).unwrap(),
+            bass: Bass::new(-3).unwrap(),
+            treble: Treble::new(7).unwrap(),
+            mute: false,
+            led: false,
+            beep: true,
+            virtual_bass: true,
+            loop_mode: LoopMode::Shuffle,
+            channel: AudioChannel::Right,
+        }
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn encode_decode() {
+        let record = scene().encode();
+
+        assert_eq!(&record[..10], b"U2\x01\x03\x2d\xfd\x07\x0c\x03\x01");
+        assert_eq!(Scene::decode(&record).unwrap(), scene());
+    }
+
+    #[test]
+    fn decode_invalid() {
+        let record = scene().encode();
+
+        let mut corrupted = record;
+        corrupted[4] = 50;
+        let mut version = record;
+        version[2] = 2;
+
+        for invalid in [&record[..13], &corrupted[..], &version[..]] {
+            assert!(matches!(
+                Scene::decode(invalid),
+                Err(Error::InvalidSettings)
+            ));
+        }
+    }
+
+    #[test]
+    fn save_load() {
+        let mut store = SettingsStore::new(MemoryFlash::<1024>::new(), 256);
+
+        assert_eq!(store.load().unwrap(), None);
+
+        store.save(&scene()).unwrap();
+        assert_eq!(store.load().unwrap(), Some(scene()));
+
+        let quiet = Scene {
+            volume: Volume::new(10).unwrap(),
+            ..scene()
+        };
+        store.save(&quiet).unwrap();
+        assert_eq!(store.load().unwrap(), Some(quiet));
+
+        let flash = store.release();
+        assert!(flash.as_bytes()[..256].iter().all(|byte| *byte == 0xFF));
+        assert_eq!(Scene::decode(&flash.as_bytes()[256..]).unwrap(), quiet);
+    }
+
+    #[test]
+    fn restore_after_reset() {
+        let mut store = SettingsStore::new(MemoryFlash::<1024>::new(), 0);
+        let mut driver = Up2StreamBuilder::new()
+            .build(Simulator::new(), Combined)
+            .unwrap();
+
+        driver.apply(&scene()).unwrap();
+        driver.save_settings(&mut store).unwrap();
+
+        driver
+            .execute_system_control(crate::SystemControl::Reset)
+            .unwrap();
+        assert_ne!(driver.snapshot().unwrap(), scene());
+
+        assert!(driver.restore_after_boot(&mut store).unwrap());
+        assert_eq!(driver.snapshot().unwrap(), scene());
+    }
+}
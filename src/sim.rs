@@ -0,0 +1,879 @@
+//! An in-process simulation of the **Arylic Up2Stream Pro** board.
+//!
+//! The [Simulator] implements the `embedded_hal::serial::{Read, Write}` traits and can be used
+//! instead of a real UART, so that application logic can be run against a stateful stand-in for the board.
+//! It keeps the state of the board (volume, source, mute, bass, treble, LED etc.) in a [BoardState],
+//! applies the commands it receives and answers queries in the same way as the board firmware.
+//!
+//! Faults on the serial line, such as noise, dropped bytes and reboots of the board, can be injected with [Faults].
+//!
+//! This module is only available with the `sim` feature.
+//!
+//! # Example
+//! ```
+//! use up2stream_uart::{Up2Stream, Source, Volume};
+//! use up2stream_uart::sim::Simulator;
+//!
+//! let mut up2stream_device = Up2Stream::new_combined(Simulator::new());
+//!
+//! up2stream_device.select_input_source(Source::LineIn).unwrap();
+//! up2stream_device.set_volume(Volume::new(20).unwrap()).unwrap();
+//!
+//! assert_eq!(up2stream_device.input_source().unwrap(), Source::LineIn);
+//!
+//! // Check the state of the simulated board
+//! let (simulator, _) = up2stream_device.release();
+//! assert_eq!(simulator.state().volume, Volume::new(20).unwrap());
+//! ```
+
+use core::convert::Infallible;
+use core::fmt::Write as _;
+use core::str::FromStr;
+
+use arrayvec::{ArrayString, ArrayVec};
+use embedded_hal::serial::{Read, Write};
+
+use crate::parameter_types::{
+    AudioChannel, Bass, LoopMode, PlayPreset, ScalarParameter, Source, Switch, Treble, Volume,
+};
+use crate::{PARAMETER_START, TERMINATOR};
+
+/// The maximum length of the device name held by the simulator.
+pub const MAX_NAME_LENGTH: usize = 32;
+
+// The longest frame that is accepted from the driver. Longer frames are discarded.
+const MAX_FRAME_LENGTH: usize = 64;
+
+// The number of bytes that can be waiting to be read by the driver.
+const MAX_PENDING: usize = 256;
+
+/// The text sent by the simulated board when it boots, unless another banner is set in [Faults].
+pub const BOOT_BANNER: &[u8] = b"\r\nBOOT\r\nUP2STREAM-PRO\r\nREADY\r\n";
+
+/// The state of the simulated board.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BoardState {
+    /// The firmware version reported with `VER`
+    pub firmware_version: ArrayString<32>,
+    /// The current input source
+    pub source: Source,
+    /// The current volume
+    pub volume: Volume,
+    /// If the audio is muted
+    pub mute: bool,
+    /// The current setting for the bass filter
+    pub bass: Bass,
+    /// The current setting for the treble filter
+    pub treble: Treble,
+    /// If the LEDs are enabled
+    pub led: bool,
+    /// If the beep sound is enabled
+    pub beep: bool,
+    /// If virtual bass is enabled
+    pub virtual_bass: bool,
+    /// The loop mode for network playback
+    pub loop_mode: LoopMode,
+    /// The audio channel that is output
+    pub channel: AudioChannel,
+    /// The device name
+    pub name: ArrayString<MAX_NAME_LENGTH>,
+    /// If audio output is enabled
+    pub audio_out: bool,
+    /// If the device is connected to Wifi
+    pub net: bool,
+    /// If the device is physically connected to internet
+    pub internet: bool,
+    /// If the device is connected to ethernet
+    pub ethernet: bool,
+    /// If the device is playing
+    pub playing: bool,
+    /// If the device is currently upgrading it's firmware
+    pub upgrading: bool,
+    /// If a bluetooth device is connected
+    pub bluetooth_connected: bool,
+    /// The last preset that was played
+    pub preset: Option<PlayPreset>,
+    /// If the device is in standby
+    pub standby: bool,
+}
+
+impl Default for BoardState {
+    /// The state of the board after a factory reset.
+    fn default() -> Self {
+        BoardState {
+            firmware_version: ArrayString::from("4.2.8020-0-3").unwrap_or_default(),
+            source: Source::Net,
+            volume: Volume(30),
+            mute: false,
+            bass: Bass(0),
+            treble: Treble(0),
+            led: true,
+            beep: true,
+            virtual_bass: false,
+            loop_mode: LoopMode::Sequence,
+            channel: AudioChannel::Stereo,
+            name: ArrayString::from("Up2Stream Pro").unwrap_or_default(),
+            audio_out: true,
+            net: true,
+            internet: true,
+            ethernet: false,
+            playing: false,
+            upgrading: false,
+            bluetooth_connected: false,
+            preset: None,
+            standby: false,
+        }
+    }
+}
+
+/// The faults injected by the simulator into the data it sends.
+///
+/// Probabilities are given in parts per thousand. Faults are generated by a pseudo random number
+/// generator started with `seed`, so that a failure can be reproduced by using the same seed.
+///
+/// Noise is only added between responses, as the UART API has no way of detecting
+/// noise within a response.
+///
+/// # Example
+/// ```
+/// use up2stream_uart::{Combined, Up2StreamBuilder, Volume};
+/// use up2stream_uart::sim::{Faults, Simulator};
+///
+/// let faults = Faults {
+///     seed: 42,
+///     noise: 100,
+///     would_block: 50,
+///     truncate: 20,
+///     ..Faults::default()
+/// };
+///
+/// let mut up2stream_device = Up2StreamBuilder::new()
+///     .timeout_polls(100)
+///     .retries(5)
+///     .build(Simulator::with_faults(faults), Combined)
+///     .unwrap();
+///
+/// assert_eq!(up2stream_device.volume().unwrap(), Volume::new(30).unwrap());
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Faults {
+    /// The seed for generating the faults
+    pub seed: u64,
+    /// Probability that noise is sent before a response
+    pub noise: u16,
+    /// The maximum number of noise bytes sent at a time
+    pub max_noise_length: u8,
+    /// Probability that a sent byte is dropped
+    pub drop: u16,
+    /// Probability that a read starts a stretch of reads returning `WouldBlock`
+    pub would_block: u16,
+    /// The maximum number of reads in a stretch returning `WouldBlock`
+    pub max_would_block: u16,
+    /// Probability that a response is cut short
+    pub truncate: u16,
+    /// Probability that the boot banner is sent in the middle of a response
+    pub boot_banner: u16,
+    /// Probability that the board reboots when receiving a frame, losing the frame
+    pub reboot: u16,
+    /// The text sent by the board when booting
+    pub banner: &'static [u8],
+}
+
+impl Default for Faults {
+    /// No faults
+    fn default() -> Self {
+        Faults {
+            seed: 0,
+            noise: 0,
+            max_noise_length: 8,
+            drop: 0,
+            would_block: 0,
+            max_would_block: 16,
+            truncate: 0,
+            boot_banner: 0,
+            reboot: 0,
+            banner: BOOT_BANNER,
+        }
+    }
+}
+
+// A xorshift pseudo random number generator
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // The state must not be zero
+        Rng(seed ^ 0x9E37_79B9_7F4A_7C15)
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    // A value in 0..bound
+    fn below(&mut self, bound: u64) -> u64 {
+        if bound == 0 {
+            0
+        } else {
+            self.next() % bound
+        }
+    }
+
+    // True with the probability given in parts per thousand
+    fn chance(&mut self, per_mille: u16) -> bool {
+        per_mille > 0 && self.below(1000) < per_mille as u64
+    }
+}
+
+/// A simulated board that is connected to the driver through the `embedded_hal::serial::{Read, Write}` traits.
+///
+/// As the simulator implements both traits, the driver is created with [Up2Stream::new_combined](crate::Up2Stream::new_combined).
+#[derive(Debug, Clone)]
+pub struct Simulator {
+    state: BoardState,
+
+    faults: Faults,
+    rng: Rng,
+    // The number of reads that still return WouldBlock
+    blocked_reads: u16,
+
+    // The frame currently being received from the driver
+    frame: ArrayVec<u8, MAX_FRAME_LENGTH>,
+    frame_overflow: bool,
+
+    // The bytes sent by the board and not yet read by the driver
+    pending: ArrayVec<u8, MAX_PENDING>,
+    pending_index: usize,
+}
+
+impl Simulator {
+    /// Create a simulator with the board in the factory reset state.
+    pub fn new() -> Self {
+        Self::with_state(BoardState::default())
+    }
+
+    /// Create a simulator with the board in the factory reset state that injects faults.
+    pub fn with_faults(faults: Faults) -> Self {
+        let mut simulator = Self::new();
+        simulator.set_faults(faults);
+        simulator
+    }
+
+    /// Create a simulator with the board in the specified state.
+    pub fn with_state(state: BoardState) -> Self {
+        let faults = Faults::default();
+        Simulator {
+            state,
+            faults,
+            rng: Rng::new(faults.seed),
+            blocked_reads: 0,
+            frame: ArrayVec::new(),
+            frame_overflow: false,
+            pending: ArrayVec::new(),
+            pending_index: 0,
+        }
+    }
+
+    /// Get the current state of the board.
+    pub fn state(&self) -> &BoardState {
+        &self.state
+    }
+
+    /// Change the state of the board, for instance to simulate the use of the remote control.
+    pub fn state_mut(&mut self) -> &mut BoardState {
+        &mut self.state
+    }
+
+    /// Set the faults that are injected, restarting the fault generation from the seed.
+    pub fn set_faults(&mut self, faults: Faults) {
+        self.faults = faults;
+        self.rng = Rng::new(faults.seed);
+        self.blocked_reads = 0;
+    }
+
+    /// Get the faults that are injected.
+    pub fn faults(&self) -> &Faults {
+        &self.faults
+    }
+
+    /// Reboot the board. Any data not yet read is lost, playback stops and the boot banner is sent.
+    pub fn reboot(&mut self) {
+        self.pending.clear();
+        self.pending_index = 0;
+        self.frame.clear();
+        self.frame_overflow = false;
+
+        self.state.playing = false;
+        self.state.standby = false;
+
+        self.send(self.faults.banner);
+    }
+
+    /// The bytes sent by the board that have not yet been read.
+    pub fn pending(&self) -> &[u8] {
+        &self.pending[self.pending_index..]
+    }
+
+    /// Queue bytes to be read as if they had been sent by the board. No faults are injected.
+    /// If there is no space for the bytes then they are dropped, as happens when the receive buffer
+    /// of a UART overruns.
+    pub fn send(&mut self, bytes: &[u8]) {
+        if self.pending_index > 0 {
+            self.pending.drain(..self.pending_index);
+            self.pending_index = 0;
+        }
+        for byte in bytes {
+            if self.pending.try_push(*byte).is_err() {
+                break;
+            }
+        }
+    }
+
+    // Carry out the frame that has been received.
+    fn process_frame(&mut self) {
+        let frame = core::mem::take(&mut self.frame);
+        if core::mem::take(&mut self.frame_overflow) {
+            return;
+        }
+
+        if self.rng.chance(self.faults.reboot) {
+            self.reboot();
+            return;
+        }
+
+        // The firmware ignores anything that it does not understand
+        let Ok(frame) = core::str::from_utf8(&frame) else {
+            return;
+        };
+
+        match frame.split_once(PARAMETER_START as char) {
+            Some((command, parameter)) => self.command(command, parameter),
+            None => self.query_or_command(frame),
+        }
+    }
+
+    // Frames without parameters are either queries or commands without parameters.
+    fn query_or_command(&mut self, name: &str) {
+        let mut response = ArrayString::<MAX_FRAME_LENGTH>::new();
+        let state = &mut self.state;
+        let result = match name {
+            // The wake-up character sent by the driver results in an empty frame
+            "" => return,
+            "POP" => {
+                state.playing = !state.playing;
+                return;
+            }
+            "STP" => {
+                state.playing = false;
+                return;
+            }
+            "NXT" | "PRE" | "WRS" => return,
+            "VER" => response
+                .try_push_str(&state.firmware_version)
+                .map_err(|_| ()),
+            "STA" => {
+                let mut buf = [0; 7];
+                push_bytes(&mut response, state.source.to_parameter_str(&mut buf)).and_then(|_| {
+                    write!(
+                        response,
+                        ",{},{},{},{},{},{},{},{},{}",
+                        state.mute as u8,
+                        state.volume.get(),
+                        state.treble.get(),
+                        state.bass.get(),
+                        state.net as u8,
+                        state.internet as u8,
+                        state.playing as u8,
+                        state.led as u8,
+                        state.upgrading as u8,
+                    )
+                    .map_err(|_| ())
+                })
+            }
+            "SRC" => {
+                let mut buf = [0; 7];
+                push_bytes(&mut response, state.source.to_parameter_str(&mut buf))
+            }
+            "VOL" => write!(response, "{}", state.volume.get()).map_err(|_| ()),
+            "MUT" => write!(response, "{}", state.mute as u8).map_err(|_| ()),
+            "BAS" => write!(response, "{}", state.bass.get()).map_err(|_| ()),
+            "TRE" => write!(response, "{}", state.treble.get()).map_err(|_| ()),
+            "LED" => write!(response, "{}", state.led as u8).map_err(|_| ()),
+            "BEP" => write!(response, "{}", state.beep as u8).map_err(|_| ()),
+            "VBS" => write!(response, "{}", state.virtual_bass as u8).map_err(|_| ()),
+            "AUD" => write!(response, "{}", state.audio_out as u8).map_err(|_| ()),
+            "WWW" => write!(response, "{}", state.internet as u8).map_err(|_| ()),
+            "WIF" => write!(response, "{}", state.net as u8).map_err(|_| ()),
+            "ETH" => write!(response, "{}", state.ethernet as u8).map_err(|_| ()),
+            "PLA" => write!(response, "{}", state.playing as u8).map_err(|_| ()),
+            "BTC" => write!(response, "{}", state.bluetooth_connected as u8).map_err(|_| ()),
+            "LPM" => {
+                let mut buf = [0; 13];
+                push_bytes(&mut response, state.loop_mode.to_parameter_str(&mut buf))
+            }
+            "CHN" => {
+                let mut buf = [0; 1];
+                push_bytes(&mut response, state.channel.to_parameter_str(&mut buf))
+            }
+            "NAM" => response.try_push_str(&state.name).map_err(|_| ()),
+            _ => return,
+        };
+
+        if result.is_ok() {
+            self.respond(name, &response);
+        }
+    }
+
+    // Apply a command with a parameter. Invalid parameters are ignored.
+    fn command(&mut self, command: &str, parameter: &str) {
+        let state = &mut self.state;
+        match command {
+            "SRC" => set(&mut state.source, parameter),
+            "VOL" => set(&mut state.volume, parameter),
+            "BAS" => set(&mut state.bass, parameter),
+            "TRE" => set(&mut state.treble, parameter),
+            "LPM" => set(&mut state.loop_mode, parameter),
+            "CHN" => set(&mut state.channel, parameter),
+            "MUT" => switch(&mut state.mute, parameter),
+            "LED" => switch(&mut state.led, parameter),
+            "BEP" => switch(&mut state.beep, parameter),
+            "VBS" => switch(&mut state.virtual_bass, parameter),
+            "AUD" => switch(&mut state.audio_out, parameter),
+            "BTC" => switch(&mut state.bluetooth_connected, parameter),
+            "PST" => {
+                if let Ok(preset) = PlayPreset::from_str(parameter) {
+                    state.preset = Some(preset);
+                    state.playing = true;
+                }
+            }
+            "NAM" => {
+                if let Ok(name) = ArrayString::from(parameter) {
+                    state.name = name;
+                }
+            }
+            "SYS" => match parameter {
+                "REBOOT" => self.reboot(),
+                "STANDBY" => {
+                    state.playing = false;
+                    state.standby = true;
+                }
+                "RESET" => {
+                    *state = BoardState {
+                        firmware_version: state.firmware_version,
+                        ..BoardState::default()
+                    }
+                }
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+
+    // Queue a response to a query, injecting any faults.
+    fn respond(&mut self, name: &str, parameter: &str) {
+        let mut response = ArrayVec::<u8, MAX_FRAME_LENGTH>::new();
+        response.extend(
+            name.bytes()
+                .chain([PARAMETER_START])
+                .chain(parameter.bytes())
+                .chain([TERMINATOR])
+                .take(MAX_FRAME_LENGTH),
+        );
+
+        if self.rng.chance(self.faults.noise) {
+            let length = 1 + self.rng.below(self.faults.max_noise_length as u64);
+            for _ in 0..length {
+                let noise = self.rng.next() as u8;
+                self.send_faulty(noise);
+            }
+        }
+
+        let length = if self.rng.chance(self.faults.truncate) {
+            self.rng.below(response.len() as u64) as usize
+        } else {
+            response.len()
+        };
+
+        let banner_position = if self.rng.chance(self.faults.boot_banner) {
+            Some(self.rng.below(length as u64 + 1) as usize)
+        } else {
+            None
+        };
+
+        for (position, byte) in response[..length].iter().enumerate() {
+            if banner_position == Some(position) {
+                self.send_banner();
+            }
+            self.send_faulty(*byte);
+        }
+        if banner_position == Some(length) {
+            self.send_banner();
+        }
+    }
+
+    fn send_banner(&mut self) {
+        for byte in self.faults.banner {
+            self.send_faulty(*byte);
+        }
+    }
+
+    // Send a byte unless it is dropped
+    fn send_faulty(&mut self, byte: u8) {
+        if !self.rng.chance(self.faults.drop) {
+            self.send(&[byte]);
+        }
+    }
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Read<u8> for Simulator {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        if self.blocked_reads == 0 && self.rng.chance(self.faults.would_block) {
+            self.blocked_reads = 1 + self.rng.below(self.faults.max_would_block as u64) as u16;
+        }
+        if self.blocked_reads > 0 {
+            self.blocked_reads -= 1;
+            return Err(nb::Error::WouldBlock);
+        }
+
+        match self.pending.get(self.pending_index) {
+            Some(byte) => {
+                self.pending_index += 1;
+                Ok(*byte)
+            }
+            None => Err(nb::Error::WouldBlock),
+        }
+    }
+}
+
+impl Write<u8> for Simulator {
+    type Error = Infallible;
+
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        if word == TERMINATOR {
+            self.process_frame();
+        } else if self.frame.try_push(word).is_err() {
+            self.frame_overflow = true;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+fn push_bytes<const N: usize>(s: &mut ArrayString<N>, bytes: &[u8]) -> Result<(), ()> {
+    let bytes = core::str::from_utf8(bytes).map_err(|_| ())?;
+    s.try_push_str(bytes).map_err(|_| ())
+}
+
+fn set<T: FromStr>(value: &mut T, parameter: &str) {
+    if let Ok(v) = T::from_str(parameter) {
+        *value = v;
+    }
+}
+
+fn switch(value: &mut bool, parameter: &str) {
+    match Switch::from_str(parameter) {
+        Ok(Switch::Toggle) => *value = !*value,
+        Ok(switch) => *value = switch.to_bool().unwrap_or(*value),
+        Err(_) => (),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{Combined, DeviceStatus, Error, SystemControl, Up2Stream, Up2StreamBuilder};
+
+    #[test]
+    fn query_volume() -> Result<(), Error> {
+        let state = BoardState {
+            volume: Volume::new(42)?,
+            ..Default::default()
+        };
+
+        let mut up2stream_device = Up2Stream::new_combined(Simulator::with_state(state));
+
+        assert_eq!(up2stream_device.volume()?, Volume::new(42)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn set_and_query() -> Result<(), Error> {
+        let mut up2stream_device = Up2Stream::new_combined(Simulator::new());
+
+        up2stream_device.set_volume(Volume::new(65)?)?;
+        up2stream_device.set_bass(Bass::new(-3)?)?;
+        up2stream_device.set_treble(Treble::new(7)?)?;
+        up2stream_device.select_input_source(Source::Bluetooth)?;
+        up2stream_device.set_mute(Switch::On)?;
+
+        assert_eq!(up2stream_device.volume()?, Volume::new(65)?);
+        assert_eq!(up2stream_device.bass()?, Bass::new(-3)?);
+        assert_eq!(up2stream_device.treble()?, Treble::new(7)?);
+        assert_eq!(up2stream_device.input_source()?, Source::Bluetooth);
+        assert!(up2stream_device.mute_status()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn status() -> Result<(), Error> {
+        let mut up2stream_device = Up2Stream::new_combined(Simulator::new());
+
+        up2stream_device.select_input_source(Source::LineIn)?;
+        up2stream_device.set_treble(Treble::new(-4)?)?;
+
+        let expected_status = DeviceStatus {
+            source: Source::LineIn,
+            mute: false,
+            volume: Volume::new(30)?,
+            treble: Treble::new(-4)?,
+            bass: Bass::new(0)?,
+            net: true,
+            internet: true,
+            playing: false,
+            led: true,
+            upgrading: false,
+        };
+
+        assert_eq!(up2stream_device.status()?, expected_status);
+
+        Ok(())
+    }
+
+    #[test]
+    fn toggle_mute() -> Result<(), Error> {
+        let mut up2stream_device = Up2Stream::new_combined(Simulator::new());
+
+        up2stream_device.set_mute(Switch::Toggle)?;
+        assert!(up2stream_device.mute_status()?);
+
+        up2stream_device.set_mute(Switch::Toggle)?;
+        assert!(!up2stream_device.mute_status()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_parameters_ignored() {
+        let mut simulator = Simulator::new();
+
+        for byte in b"VOL:101;BAS:X;SRC:RADIO;" {
+            simulator.write(*byte).unwrap();
+        }
+
+        assert_eq!(simulator.state(), &BoardState::default());
+    }
+
+    #[test]
+    fn commands_have_no_response() {
+        let mut simulator = Simulator::new();
+
+        for byte in b";VOL:10;MUT:1;POP;" {
+            simulator.write(*byte).unwrap();
+        }
+
+        assert!(simulator.pending().is_empty());
+        assert!(simulator.state().playing);
+    }
+
+    #[test]
+    fn query_response() {
+        let mut simulator = Simulator::new();
+
+        for byte in b"LPM;CHN;NAM;" {
+            simulator.write(*byte).unwrap();
+        }
+
+        assert_eq!(
+            simulator.pending(),
+            b"LPM:SEQUENCE;CHN:S;NAM:Up2Stream Pro;"
+        );
+    }
+
+    #[test]
+    fn read_firmware_version_and_name() -> Result<(), Error> {
+        let mut up2stream_device = Up2Stream::new_combined(Simulator::new());
+
+        assert_eq!(up2stream_device.firmware_version()?, "4.2.8020-0-3");
+        assert_eq!(up2stream_device.raw_query("NAM")?.as_str(), "Up2Stream Pro");
+
+        Ok(())
+    }
+
+    #[test]
+    fn factory_reset() -> Result<(), Error> {
+        let mut up2stream_device = Up2Stream::new_combined(Simulator::new());
+
+        up2stream_device.set_volume(Volume::new(80)?)?;
+        up2stream_device.execute_system_control(SystemControl::Reset)?;
+
+        assert_eq!(up2stream_device.volume()?, Volume::new(30)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn playback_commands() -> Result<(), Error> {
+        let mut up2stream_device = Up2Stream::new_combined(Simulator::new());
+
+        up2stream_device.play_pause_toggle()?;
+        up2stream_device.next_track()?;
+
+        let (mut simulator, _) = up2stream_device.release();
+        assert!(simulator.state().playing);
+
+        simulator.state_mut().source = Source::Bluetooth;
+        simulator.state_mut().bluetooth_connected = true;
+
+        let mut up2stream_device = Up2Stream::new_combined(simulator);
+        assert!(up2stream_device.bluetooth_connected()?);
+
+        up2stream_device.disconnect_bluetooth()?;
+        assert!(!up2stream_device.bluetooth_connected()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn faults_reproducible() {
+        let faults = Faults {
+            seed: 7,
+            noise: 300,
+            drop: 50,
+            truncate: 100,
+            boot_banner: 100,
+            ..Faults::default()
+        };
+
+        let mut simulator1 = Simulator::with_faults(faults);
+        let mut simulator2 = Simulator::with_faults(faults);
+
+        for byte in b"VOL;STA;SRC;NAM;VER;" {
+            simulator1.write(*byte).unwrap();
+            simulator2.write(*byte).unwrap();
+        }
+
+        assert_eq!(simulator1.pending(), simulator2.pending());
+        assert_ne!(
+            simulator1.pending(),
+            b"VOL:30;STA:NET,0,30,0,0,1,1,0,1,0;SRC:NET;NAM:Up2Stream Pro;VER:4.2.8020-0-3;"
+        );
+    }
+
+    #[test]
+    fn reboot() {
+        let mut simulator = Simulator::new();
+        simulator.state_mut().playing = true;
+        simulator.send(b"VOL:3");
+
+        simulator.reboot();
+
+        assert_eq!(simulator.pending(), BOOT_BANNER);
+        assert!(!simulator.state().playing);
+    }
+
+    #[test]
+    fn reboot_command() -> Result<(), Error> {
+        let mut up2stream_device = Up2StreamBuilder::new().build(Simulator::new(), Combined)?;
+        up2stream_device.set_volume(Volume::new(42)?)?;
+        up2stream_device.play_pause_toggle()?;
+
+        up2stream_device.execute_system_control(SystemControl::Reboot)?;
+
+        let (simulator, writer) = up2stream_device.release();
+        assert_eq!(simulator.pending(), BOOT_BANNER);
+        assert!(!simulator.state().playing);
+
+        // The banner is skipped by the next query
+        let mut up2stream_device = Up2StreamBuilder::new()
+            .wake_up(false)
+            .build(simulator, writer)?;
+        assert_eq!(up2stream_device.volume()?, Volume::new(42)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn driver_recovers_from_noise_delays_and_truncation() -> Result<(), Error> {
+        let faults = Faults {
+            seed: 12345,
+            noise: 200,
+            would_block: 100,
+            truncate: 50,
+            boot_banner: 50,
+            ..Faults::default()
+        };
+
+        let mut up2stream_device = Up2StreamBuilder::new()
+            .timeout_polls(64)
+            .retries(8)
+            .build(Simulator::with_faults(faults), Combined)?;
+
+        for i in 0..200u8 {
+            let volume = Volume::new((i % 101) as i8)?;
+            up2stream_device.set_volume(volume)?;
+            assert_eq!(up2stream_device.volume()?, volume);
+
+            let status = up2stream_device.status()?;
+            assert_eq!(status.volume, volume);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn driver_resynchronises_after_drops_and_reboots() -> Result<(), Error> {
+        let faults = Faults {
+            seed: 999,
+            drop: 20,
+            reboot: 20,
+            would_block: 20,
+            ..Faults::default()
+        };
+
+        let mut up2stream_device = Up2StreamBuilder::new()
+            .timeout_polls(64)
+            .retries(2)
+            .build(Simulator::with_faults(faults), Combined)?;
+
+        // With dropped bytes the driver cannot always get a valid response, but
+        // it must not hang
+        for i in 0..200u8 {
+            up2stream_device
+                .set_volume(Volume::new((i % 101) as i8)?)
+                .ok();
+            up2stream_device.volume().ok();
+            up2stream_device.input_source().ok();
+        }
+
+        let (mut simulator, _) = up2stream_device.release();
+        simulator.set_faults(Faults::default());
+        let expected_volume = simulator.state().volume;
+
+        // Without faults the driver is back in step with the board
+        let mut up2stream_device = Up2Stream::new_combined(simulator);
+        assert_eq!(up2stream_device.volume()?, expected_volume);
+        assert_eq!(up2stream_device.input_source()?, Source::Net);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,409 @@
+//! Remembering the volume of each input source, see [VolumeMemory].
+//!
+//! The volumes are kept in a [SourceVolumes] table that can be stored in a binary record of
+//! [ENCODED_SOURCE_VOLUMES_LENGTH] bytes (little endian):
+//!
+//! | Bytes | Content                                                       |
+//! |-------|---------------------------------------------------------------|
+//! | 0-1   | Magic `SV`                                                    |
+//! | 2     | Version of the encoding, [SOURCE_VOLUMES_VERSION]             |
+//! | 3-7   | Volume of NET, USB, BT, LINE-IN and COAX, `0xFF` if not known |
+//! | 8-11  | CRC-32 of bytes 0-7                                           |
+
+use embedded_hal::serial::Read;
+
+use crate::error::Error;
+use crate::serial::Transmit;
+use crate::settings::{code, crc32, SOURCES};
+use crate::{ScalarParameter, Source, Up2Stream, Volume, DEFAULT_RESPONSE_SIZE};
+
+/// The length in bytes of the encoded [SourceVolumes].
+pub const ENCODED_SOURCE_VOLUMES_LENGTH: usize = 12;
+
+/// The version of the encoding of [SourceVolumes]. Records with a different version are rejected.
+pub const SOURCE_VOLUMES_VERSION: u8 = 1;
+
+const MAGIC: [u8; 2] = *b"SV";
+const VOLUMES_OFFSET: usize = 3;
+const CRC_OFFSET: usize = ENCODED_SOURCE_VOLUMES_LENGTH - 4;
+const UNKNOWN: u8 = 0xFF;
+
+/// The last volume used on each [Source].
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct SourceVolumes {
+    volumes: [Option<Volume>; SOURCES.len()],
+}
+
+impl SourceVolumes {
+    /// A table without any volumes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The volume remembered for `source`.
+    pub fn get(&self, source: Source) -> Option<Volume> {
+        self.volumes[code(&SOURCES, &source) as usize]
+    }
+
+    /// Remember `volume` for `source`.
+    pub fn set(&mut self, source: Source, volume: Volume) {
+        self.volumes[code(&SOURCES, &source) as usize] = Some(volume);
+    }
+
+    /// Forget the volume of `source`, so that switching to it keeps the current volume.
+    pub fn forget(&mut self, source: Source) {
+        self.volumes[code(&SOURCES, &source) as usize] = None;
+    }
+
+    /// Encode the table into a binary record, e.g. to store it in flash.
+    pub fn encode(&self) -> [u8; ENCODED_SOURCE_VOLUMES_LENGTH] {
+        let mut record = [0; ENCODED_SOURCE_VOLUMES_LENGTH];
+        record[..2].copy_from_slice(&MAGIC);
+        record[2] = SOURCE_VOLUMES_VERSION;
+        for (byte, volume) in record[VOLUMES_OFFSET..CRC_OFFSET]
+            .iter_mut()
+            .zip(self.volumes)
+        {
+            *byte = volume.map_or(UNKNOWN, |volume| volume.get() as u8);
+        }
+
+        let crc = crc32(&record[..CRC_OFFSET]);
+        record[CRC_OFFSET..].copy_from_slice(&crc.to_le_bytes());
+
+        record
+    }
+
+    /// Decode a binary record encoded with [SourceVolumes::encode].
+    ///
+    /// Returns [Error::InvalidSettings] if the record is too short, has a different version or
+    /// its CRC or one of its volumes is invalid.
+    pub fn decode(record: &[u8]) -> Result<Self, Error> {
+        let record = record
+            .get(..ENCODED_SOURCE_VOLUMES_LENGTH)
+            .ok_or(Error::InvalidSettings)?;
+
+        let crc = u32::from_le_bytes([record[8], record[9], record[10], record[11]]);
+        if record[..2] != MAGIC
+            || record[2] != SOURCE_VOLUMES_VERSION
+            || crc32(&record[..CRC_OFFSET]) != crc
+        {
+            return Err(Error::InvalidSettings);
+        }
+
+        let mut table = Self::new();
+        for (volume, byte) in table
+            .volumes
+            .iter_mut()
+            .zip(&record[VOLUMES_OFFSET..CRC_OFFSET])
+        {
+            if *byte != UNKNOWN {
+                let value = i8::try_from(*byte).map_err(|_| Error::InvalidSettings)?;
+                *volume = Some(Volume::new(value).map_err(|_| Error::InvalidSettings)?);
+            }
+        }
+
+        Ok(table)
+    }
+}
+
+/// Restores the last volume used on a source when switching to it, so that sources with
+/// different levels, e.g. a hot line-in and a network stream, play at their own volume.
+///
+/// The volume of the source that is switched away from is read from the board, so changes made
+/// with the remote or the app are remembered too.
+///
+/// # Example
+/// ```
+/// use up2stream_uart::{Source, SourceVolumes, Up2Stream, VolumeMemory};
+/// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+/// # let mut serial = SerialMock::new(&[
+/// #     SerialTransaction::write(b';'),
+/// #     SerialTransaction::write_many(b"SRC;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::read_many(b"SRC:NET;"),
+/// #     SerialTransaction::write_many(b"VOL;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::read_many(b"VOL:60;"),
+/// #     SerialTransaction::write_many(b"VOL:35;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::write_many(b"SRC:LINE-IN;"),
+/// #     SerialTransaction::flush(),
+/// # ]);
+/// # let up2stream_driver = Up2Stream::new_combined(serial.clone());
+/// let mut volumes = SourceVolumes::new();
+/// volumes.set(Source::LineIn, up2stream_uart::volume!(35));
+///
+/// let mut up2stream_device = VolumeMemory::new(up2stream_driver, volumes);
+///
+/// // Lowers the volume to 35 before switching, and remembers 60 for the network
+/// up2stream_device.select_input_source(Source::LineIn).unwrap();
+/// # serial.done();
+/// ```
+pub struct VolumeMemory<R, W, const N: usize = DEFAULT_RESPONSE_SIZE>
+where
+    R: Read<u8>,
+    W: Transmit<R>,
+{
+    driver: Up2Stream<R, W, N>,
+    volumes: SourceVolumes,
+}
+
+impl<R, W, const N: usize> VolumeMemory<R, W, N>
+where
+    R: Read<u8>,
+    W: Transmit<R>,
+{
+    /// Remember the volume of each source of the board controlled by `driver`, starting with
+    /// `volumes`, e.g. decoded from flash.
+    pub fn new(driver: Up2Stream<R, W, N>, volumes: SourceVolumes) -> Self {
+        VolumeMemory { driver, volumes }
+    }
+
+    /// The driver, e.g. to control the board between source changes.
+    pub fn driver(&mut self) -> &mut Up2Stream<R, W, N> {
+        &mut self.driver
+    }
+
+    /// The remembered volumes, e.g. to encode them.
+    pub fn volumes(&mut self) -> &mut SourceVolumes {
+        &mut self.volumes
+    }
+
+    /// Give back the driver and the remembered volumes.
+    pub fn release(self) -> (Up2Stream<R, W, N>, SourceVolumes) {
+        (self.driver, self.volumes)
+    }
+
+    /// Remember the current volume for the current source and return both, e.g. before the
+    /// volumes are saved.
+    pub fn remember(&mut self) -> Result<(Source, Volume), Error> {
+        let source = self.driver.input_source()?;
+        let volume = self.driver.volume()?;
+        self.volumes.set(source, volume);
+
+        Ok((source, volume))
+    }
+
+    /// Select the input source and restore the volume remembered for it, returning the volume that
+    /// the source plays at. The volume of the current source is remembered first.
+    ///
+    /// A lower volume is set before switching and a higher one after, so the new source is never
+    /// louder than either volume. If no volume is remembered for `source`, the volume is kept.
+    ///
+    /// If switching fails after the volume was lowered, the volume of the current source is
+    /// restored and the error of switching is returned.
+    pub fn select_input_source(&mut self, source: Source) -> Result<Volume, Error> {
+        let (_, current) = self.remember()?;
+
+        let target = match self.volumes.get(source) {
+            Some(volume) => volume,
+            None => {
+                self.driver.select_input_source(source)?;
+                self.volumes.set(source, current);
+                return Ok(current);
+            }
+        };
+
+        if target.get() < current.get() {
+            self.driver.set_volume(target)?;
+            if let Err(error) = self.driver.select_input_source(source) {
+                // The current source keeps playing, so it gets its volume back
+                self.driver.set_volume(current).ok();
+                return Err(error);
+            }
+        } else {
+            self.driver.select_input_source(source)?;
+            if target != current {
+                self.driver.set_volume(target)?;
+            }
+        }
+
+        Ok(target)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+    use embedded_hal_mock::MockError;
+
+    use crate::sim::Simulator;
+    use crate::{Combined, Up2StreamBuilder};
+
+    #[test]
+    fn table() {
+        let mut volumes = SourceVolumes::new();
+        assert_eq!(volumes.get(Source::Net), None);
+
+        volumes.set(Source::Net, Volume(40));
+        volumes.set(Source::Coax, Volume(0));
+        volumes.set(Source::Coax, Volume(100));
+        assert_eq!(volumes.get(Source::Net), Some(Volume(40)));
+        assert_eq!(volumes.get(Source::Coax), Some(Volume(100)));
+
+        volumes.forget(Source::Net);
+        assert_eq!(volumes.get(Source::Net), None);
+    }
+
+    #[test]
+    fn encode_decode() {
+        let mut volumes = SourceVolumes::new();
+        volumes.set(Source::Usb, Volume(0));
+        volumes.set(Source::LineIn, Volume(25));
+
+        let record = volumes.encode();
+        assert_eq!(&record[..8], b"SV\x01\xFF\x00\xFF\x19\xFF");
+        assert_eq!(SourceVolumes::decode(&record).unwrap(), volumes);
+
+        let empty = SourceVolumes::new().encode();
+        assert_eq!(SourceVolumes::decode(&empty).unwrap(), SourceVolumes::new());
+    }
+
+    #[test]
+    fn decode_invalid() {
+        let record = SourceVolumes::new().encode();
+
+        let mut corrupted = record;
+        corrupted[4] = 50;
+        let mut version = record;
+        version[2] = SOURCE_VOLUMES_VERSION + 1;
+
+        for invalid in [&record[..11], &corrupted[..], &version[..], &[0xFF; 12][..]] {
+            assert!(matches!(
+                SourceVolumes::decode(invalid),
+                Err(Error::InvalidSettings)
+            ));
+        }
+
+        // A valid CRC over a volume out of range
+        let mut out_of_range = record;
+        out_of_range[5] = 101;
+        let crc = crc32(&out_of_range[..CRC_OFFSET]);
+        out_of_range[CRC_OFFSET..].copy_from_slice(&crc.to_le_bytes());
+        assert!(matches!(
+            SourceVolumes::decode(&out_of_range),
+            Err(Error::InvalidSettings)
+        ));
+    }
+
+    #[test]
+    fn restores_volume_per_source() {
+        let driver = Up2StreamBuilder::new()
+            .build(Simulator::new(), Combined)
+            .unwrap();
+        let mut memory = VolumeMemory::new(driver, SourceVolumes::new());
+
+        memory.driver().set_volume(Volume(60)).unwrap();
+        // Nothing remembered for line-in yet, so the volume is kept
+        assert_eq!(
+            memory.select_input_source(Source::LineIn).unwrap(),
+            Volume(60)
+        );
+
+        memory.driver().set_volume(Volume(20)).unwrap();
+        assert_eq!(memory.select_input_source(Source::Net).unwrap(), Volume(60));
+        assert_eq!(memory.driver().volume().unwrap(), Volume(60));
+
+        assert_eq!(
+            memory.select_input_source(Source::LineIn).unwrap(),
+            Volume(20)
+        );
+        assert_eq!(memory.driver().input_source().unwrap(), Source::LineIn);
+        assert_eq!(memory.driver().volume().unwrap(), Volume(20));
+
+        let (_, volumes) = memory.release();
+        assert_eq!(volumes.get(Source::Net), Some(Volume(60)));
+        assert_eq!(volumes.get(Source::LineIn), Some(Volume(20)));
+    }
+
+    #[test]
+    fn raises_volume_after_switching() {
+        let expectations = [
+            SerialTransaction::write(b';'),
+            SerialTransaction::write_many(b"SRC;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"SRC:LINE-IN;"),
+            SerialTransaction::write_many(b"VOL;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"VOL:20;"),
+            SerialTransaction::write_many(b"SRC:NET;"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(b"VOL:60;"),
+            SerialTransaction::flush(),
+        ];
+
+        let mut serial = SerialMock::new(&expectations);
+        let mut volumes = SourceVolumes::new();
+        volumes.set(Source::Net, Volume(60));
+        let mut memory = VolumeMemory::new(Up2Stream::new_combined(serial.clone()), volumes);
+
+        assert_eq!(memory.select_input_source(Source::Net).unwrap(), Volume(60));
+
+        serial.done();
+    }
+
+    #[test]
+    fn failed_switch_keeps_volume() {
+        let expectations = [
+            SerialTransaction::write(b';'),
+            SerialTransaction::write_many(b"SRC;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"SRC:NET;"),
+            SerialTransaction::write_many(b"VOL;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"VOL:60;"),
+            SerialTransaction::write_many(b"SRC:BT;"),
+            SerialTransaction::flush_error(nb::Error::Other(MockError::Io(
+                std::io::ErrorKind::Other,
+            ))),
+        ];
+
+        let mut serial = SerialMock::new(&expectations);
+        let mut volumes = SourceVolumes::new();
+        volumes.set(Source::Bluetooth, Volume(80));
+        let mut memory = VolumeMemory::new(Up2Stream::new_combined(serial.clone()), volumes);
+
+        assert!(matches!(
+            memory.select_input_source(Source::Bluetooth),
+            Err(Error::SendCommand)
+        ));
+        assert_eq!(memory.volumes().get(Source::Net), Some(Volume(60)));
+
+        serial.done();
+    }
+
+    #[test]
+    fn failed_switch_restores_lowered_volume() {
+        let expectations = [
+            SerialTransaction::write(b';'),
+            SerialTransaction::write_many(b"SRC;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"SRC:NET;"),
+            SerialTransaction::write_many(b"VOL;"),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(b"VOL:60;"),
+            SerialTransaction::write_many(b"VOL:35;"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(b"SRC:LINE-IN;"),
+            SerialTransaction::flush_error(nb::Error::Other(MockError::Io(
+                std::io::ErrorKind::Other,
+            ))),
+            SerialTransaction::write_many(b"VOL:60;"),
+            SerialTransaction::flush(),
+        ];
+
+        let mut serial = SerialMock::new(&expectations);
+        let mut volumes = SourceVolumes::new();
+        volumes.set(Source::LineIn, Volume(35));
+        let mut memory = VolumeMemory::new(Up2Stream::new_combined(serial.clone()), volumes);
+
+        assert!(matches!(
+            memory.select_input_source(Source::LineIn),
+            Err(Error::SendCommand)
+        ));
+
+        serial.done();
+    }
+}
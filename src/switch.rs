@@ -0,0 +1,329 @@
+//! Switching the input source without an audible thump, see [Up2Stream::switch_source_smoothly].
+
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::serial::Read;
+
+use crate::error::Error;
+use crate::ramp::SILENT;
+use crate::serial::Transmit;
+use crate::{Source, Switch, Up2Stream, Volume};
+
+/// The pauses of [Up2Stream::switch_source_smoothly] in milliseconds.
+///
+/// The defaults are conservative guesses, tune them for the amplifier.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SwitchPauses {
+    /// Ramp the volume down over this time before muting and up again after unmuting, 0 to only
+    /// mute
+    pub fade_ms: u32,
+    /// Pause after muting before the source is switched
+    pub settle_ms: u32,
+    /// Pause before each query confirming the new source
+    pub confirm_interval_ms: u32,
+    /// The number of queries confirming the new source before giving up with [Error::Timeout],
+    /// 0 to not wait for the confirmation
+    pub confirm_attempts: u8,
+    /// Pause after the new source is confirmed before the audio is restored
+    pub unmute_delay_ms: u32,
+}
+
+impl Default for SwitchPauses {
+    fn default() -> Self {
+        SwitchPauses {
+            fade_ms: 0,
+            settle_ms: 50,
+            confirm_interval_ms: 100,
+            confirm_attempts: 10,
+            unmute_delay_ms: 200,
+        }
+    }
+}
+
+impl<R, W, const N: usize> Up2Stream<R, W, N>
+where
+    R: Read<u8>,
+    W: Transmit<R>,
+{
+    /// Select the input source while the audio is muted, to avoid the thump of switching the
+    /// inputs of the amplifier.
+    ///
+    /// The audio is muted, or faded out if [SwitchPauses::fade_ms] is set, the source is switched
+    /// and the board is queried until it reports the new source. Then the previous mute state and
+    /// volume are restored. A board that was muted before stays muted.
+    ///
+    /// If a step fails, the mute state and volume are still restored before the error of the step
+    /// is returned. If the board never reports the new source, [Error::Timeout] is returned.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use up2stream_uart::{Source, SwitchPauses, Up2Stream};
+    /// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+    /// # use embedded_hal_mock::delay::MockNoop;
+    /// # let mut uart = SerialMock::new(&[SerialTransaction::read(b';')]);
+    /// # let mut up2stream_driver = Up2Stream::new_combined(uart);
+    /// # let mut delay = MockNoop::new();
+    /// let pauses = SwitchPauses {
+    ///     fade_ms: 300,
+    ///     ..SwitchPauses::default()
+    /// };
+    ///
+    /// up2stream_driver
+    ///     .switch_source_smoothly(Source::LineIn, &pauses, &mut delay)
+    ///     .unwrap();
+    /// ```
+    pub fn switch_source_smoothly<D: DelayMs<u32>>(
+        &mut self,
+        source: Source,
+        pauses: &SwitchPauses,
+        delay: &mut D,
+    ) -> Result<(), Error> {
+        let was_muted = self.mute_status()?;
+        // Only faded if the audio is playing
+        let volume = if pauses.fade_ms > 0 && !was_muted {
+            Some(self.volume()?)
+        } else {
+            None
+        };
+
+        let switched = self.switch_silenced(source, volume, was_muted, pauses, delay);
+
+        // Restored even if switching failed, the error of switching is reported first
+        let restored = self.restore_audio(volume, was_muted, switched.is_ok(), pauses, delay);
+        switched.and(restored)
+    }
+
+    fn switch_silenced<D: DelayMs<u32>>(
+        &mut self,
+        source: Source,
+        volume: Option<Volume>,
+        was_muted: bool,
+        pauses: &SwitchPauses,
+        delay: &mut D,
+    ) -> Result<(), Error> {
+        if let Some(volume) = volume {
+            self.ramp_volume_from(volume, SILENT, pauses.fade_ms, delay, || false)?;
+        }
+        if !was_muted {
+            self.set_mute(Switch::On)?;
+        }
+        delay.delay_ms(pauses.settle_ms);
+
+        self.select_input_source(source)?;
+        self.confirm_source(source, pauses, delay)?;
+
+        delay.delay_ms(pauses.unmute_delay_ms);
+        Ok(())
+    }
+
+    fn confirm_source<D: DelayMs<u32>>(
+        &mut self,
+        source: Source,
+        pauses: &SwitchPauses,
+        delay: &mut D,
+    ) -> Result<(), Error> {
+        if pauses.confirm_attempts == 0 {
+            return Ok(());
+        }
+
+        for _ in 0..pauses.confirm_attempts {
+            delay.delay_ms(pauses.confirm_interval_ms);
+            match self.input_source() {
+                Ok(reported) if reported == source => return Ok(()),
+                // The board may not answer while it switches
+                Ok(_) | Err(Error::Timeout) => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        Err(Error::Timeout)
+    }
+
+    fn restore_audio<D: DelayMs<u32>>(
+        &mut self,
+        volume: Option<Volume>,
+        was_muted: bool,
+        switched: bool,
+        pauses: &SwitchPauses,
+        delay: &mut D,
+    ) -> Result<(), Error> {
+        // Unmuting is attempted even if restoring the volume fails, and the other way round
+        let unmuted = if was_muted {
+            Ok(())
+        } else {
+            self.set_mute(Switch::Off)
+        };
+
+        let volume_restored = match volume {
+            // The fade out completed, so the volume is 0
+            Some(volume) if switched => self
+                .ramp_volume_from(SILENT, volume, pauses.fade_ms, delay, || false)
+                .map(|_| ()),
+            Some(volume) => self.set_volume(volume),
+            None => Ok(()),
+        };
+
+        unmuted.and(volume_restored)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+    use embedded_hal_mock::MockError;
+
+    use crate::sim::Simulator;
+    use crate::{Combined, Up2StreamBuilder};
+
+    // Records the delays
+    struct Delay(Vec<u32>);
+
+    impl DelayMs<u32> for Delay {
+        fn delay_ms(&mut self, ms: u32) {
+            self.0.push(ms);
+        }
+    }
+
+    fn query(query: &[u8], response: &[u8]) -> [SerialTransaction<u8>; 3] {
+        [
+            SerialTransaction::write_many(query),
+            SerialTransaction::flush(),
+            SerialTransaction::read_many(response),
+        ]
+    }
+
+    fn command(command: &[u8]) -> [SerialTransaction<u8>; 2] {
+        [
+            SerialTransaction::write_many(command),
+            SerialTransaction::flush(),
+        ]
+    }
+
+    fn send_error() -> SerialTransaction<u8> {
+        SerialTransaction::flush_error(nb::Error::Other(MockError::Io(std::io::ErrorKind::Other)))
+    }
+
+    #[test]
+    fn mutes_while_switching() {
+        let mut expectations = vec![SerialTransaction::write(b';')];
+        expectations.extend(query(b"MUT;", b"MUT:0;"));
+        expectations.extend(command(b"MUT:1;"));
+        expectations.extend(command(b"SRC:LINE-IN;"));
+        expectations.extend(query(b"SRC;", b"SRC:BT;"));
+        expectations.extend(query(b"SRC;", b"SRC:LINE-IN;"));
+        expectations.extend(command(b"MUT:0;"));
+
+        let mut serial = SerialMock::new(&expectations);
+        let mut up2stream_device = Up2Stream::new_combined(serial.clone());
+        let mut delay = Delay(Vec::new());
+
+        up2stream_device
+            .switch_source_smoothly(Source::LineIn, &SwitchPauses::default(), &mut delay)
+            .unwrap();
+        assert_eq!(delay.0, [50, 100, 100, 200]);
+
+        serial.done();
+    }
+
+    #[test]
+    fn stays_muted() {
+        let mut expectations = vec![SerialTransaction::write(b';')];
+        expectations.extend(query(b"MUT;", b"MUT:1;"));
+        expectations.extend(command(b"SRC:NET;"));
+
+        let mut serial = SerialMock::new(&expectations);
+        let mut up2stream_device = Up2Stream::new_combined(serial.clone());
+        let pauses = SwitchPauses {
+            fade_ms: 500,
+            confirm_attempts: 0,
+            ..SwitchPauses::default()
+        };
+
+        up2stream_device
+            .switch_source_smoothly(Source::Net, &pauses, &mut Delay(Vec::new()))
+            .unwrap();
+
+        serial.done();
+    }
+
+    #[test]
+    fn fades_while_switching() {
+        let mut up2stream_device = Up2StreamBuilder::new()
+            .build(Simulator::new(), Combined)
+            .unwrap();
+        up2stream_device.set_volume(Volume(4)).unwrap();
+        let pauses = SwitchPauses {
+            fade_ms: 40,
+            ..SwitchPauses::default()
+        };
+        let mut delay = Delay(Vec::new());
+
+        up2stream_device
+            .switch_source_smoothly(Source::Bluetooth, &pauses, &mut delay)
+            .unwrap();
+        assert_eq!(delay.0, [10, 10, 10, 10, 50, 100, 200, 10, 10, 10, 10]);
+
+        assert_eq!(up2stream_device.input_source().unwrap(), Source::Bluetooth);
+        assert_eq!(up2stream_device.volume().unwrap(), Volume(4));
+        assert!(!up2stream_device.mute_status().unwrap());
+    }
+
+    #[test]
+    fn unmutes_after_failure() {
+        let mut expectations = vec![SerialTransaction::write(b';')];
+        expectations.extend(query(b"MUT;", b"MUT:0;"));
+        expectations.extend(query(b"VOL;", b"VOL:1;"));
+        expectations.extend(command(b"VOL:0;"));
+        expectations.extend(command(b"MUT:1;"));
+        expectations.push(SerialTransaction::write_many(b"SRC:LINE-IN;"));
+        expectations.push(send_error());
+        expectations.extend(command(b"MUT:0;"));
+        expectations.extend(command(b"VOL:1;"));
+
+        let mut serial = SerialMock::new(&expectations);
+        let mut up2stream_device = Up2Stream::new_combined(serial.clone());
+        let pauses = SwitchPauses {
+            fade_ms: 10,
+            ..SwitchPauses::default()
+        };
+
+        assert!(matches!(
+            up2stream_device.switch_source_smoothly(
+                Source::LineIn,
+                &pauses,
+                &mut Delay(Vec::new())
+            ),
+            Err(Error::SendCommand)
+        ));
+
+        serial.done();
+    }
+
+    #[test]
+    fn unmutes_if_not_confirmed() {
+        let mut expectations = vec![SerialTransaction::write(b';')];
+        expectations.extend(query(b"MUT;", b"MUT:0;"));
+        expectations.extend(command(b"MUT:1;"));
+        expectations.extend(command(b"SRC:USB;"));
+        expectations.extend(query(b"SRC;", b"SRC:NET;"));
+        expectations.extend(query(b"SRC;", b"SRC:NET;"));
+        expectations.push(SerialTransaction::write_many(b"MUT:0;"));
+        expectations.push(send_error());
+
+        let mut serial = SerialMock::new(&expectations);
+        let mut up2stream_device = Up2Stream::new_combined(serial.clone());
+        let pauses = SwitchPauses {
+            confirm_attempts: 2,
+            ..SwitchPauses::default()
+        };
+
+        // The error of the switch is reported, not the one of unmuting
+        assert!(matches!(
+            up2stream_device.switch_source_smoothly(Source::Usb, &pauses, &mut Delay(Vec::new())),
+            Err(Error::Timeout)
+        ));
+
+        serial.done();
+    }
+}
@@ -3,16 +3,18 @@ use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransac
 use super::*;
 
 #[test]
+#[allow(clippy::let_unit_value)]
 fn send_command() {
     let msg = "CMD:on;".as_bytes();
     let expectations = [
         SerialTransaction::write(b';'),
         SerialTransaction::write_many(msg),
+        SerialTransaction::flush(),
     ];
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let _result = up2stream_device
         .send_command("CMD", "on".as_bytes())
@@ -21,6 +23,121 @@ fn send_command() {
     serial.done();
 }
 
+#[test]
+fn combined_uart() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"VOL;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_many(b"VOL:50;"),
+    ];
+
+    let serial = SerialMock::new(&expectations);
+
+    let mut up2stream_device = Up2Stream::new_combined(serial);
+
+    assert_eq!(up2stream_device.volume().unwrap(), Volume::new(50).unwrap());
+
+    let (mut serial, _) = up2stream_device.release();
+
+    serial.done();
+}
+
+#[test]
+fn release() {
+    let expectations = [SerialTransaction::write(b';')];
+
+    let serial = SerialMock::new(&expectations);
+
+    let up2stream_device = Up2Stream::new(serial.clone(), serial);
+
+    // Both halves share the same mock
+    let (mut reader, _writer) = up2stream_device.release();
+
+    reader.done();
+}
+
+#[test]
+fn send_command_transmitter_busy() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"VO"),
+        SerialTransaction::write_error(b'L', nb::Error::WouldBlock),
+        SerialTransaction::write_error(b'L', nb::Error::WouldBlock),
+        SerialTransaction::write_many(b"L:34;"),
+        SerialTransaction::flush_error(nb::Error::WouldBlock),
+        SerialTransaction::flush(),
+    ];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
+
+    up2stream_device
+        .set_volume(Volume::new(34).unwrap())
+        .unwrap();
+
+    serial.done();
+}
+
+#[test]
+fn blocking_writer() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"VOL;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_many(b"VOL:50;"),
+        SerialTransaction::write_many(b"VOL:51;"),
+        SerialTransaction::flush(),
+    ];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    let mut up2stream_device = Up2Stream::new(serial.clone(), Blocking(serial.clone()));
+
+    assert_eq!(up2stream_device.volume().unwrap(), Volume::new(50).unwrap());
+    up2stream_device
+        .set_volume(Volume::new(51).unwrap())
+        .unwrap();
+
+    serial.done();
+}
+
+#[test]
+fn blocking_writer_writes_whole_frames() {
+    // Records each bulk write
+    struct Frames(Vec<Vec<u8>>);
+
+    impl embedded_hal::blocking::serial::Write<u8> for Frames {
+        type Error = ();
+
+        fn bwrite_all(&mut self, buffer: &[u8]) -> Result<(), Self::Error> {
+            self.0.push(buffer.to_vec());
+            Ok(())
+        }
+
+        fn bflush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    let expectations = [SerialTransaction::read_many(b"VOL:50;")];
+    let mut serial = SerialMock::new(&expectations);
+
+    let mut up2stream_device = Up2Stream::new(serial.clone(), Blocking(Frames(Vec::new())));
+
+    up2stream_device.set_bass(Bass::new(-3).unwrap()).unwrap();
+    assert_eq!(up2stream_device.volume().unwrap(), Volume::new(50).unwrap());
+
+    let (_, Blocking(frames)) = up2stream_device.release();
+    assert_eq!(
+        frames.0,
+        [b";".to_vec(), b"BAS:-3;".to_vec(), b"VOL;".to_vec()]
+    );
+
+    serial.done();
+}
+
 #[test]
 fn send_query() {
     let expectations = [
@@ -33,11 +150,64 @@ fn send_query() {
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
+
+    let response = up2stream_device.send_query("CMD").unwrap();
+
+    assert_eq!(response, "on");
+
+    serial.done();
+}
+
+#[test]
+fn send_query_reuses_response_buffer() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"CMD;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_many(b"CMD:BT,1,456,PARA;"),
+        SerialTransaction::write_many(b"CMD;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_many(b"CMD:on;"),
+    ];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.send_query("CMD").unwrap();
+    assert_eq!(response, "BT,1,456,PARA");
+
+    // The second, shorter, response should not contain anything from the first
+    let response = up2stream_device.send_query("CMD").unwrap();
+    assert_eq!(response, "on");
+
+    serial.done();
+}
+
+#[test]
+fn send_query_response_too_long() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"CMD;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_many(b"CMD:BT,1,"),
+        SerialTransaction::write_many(b"CMD;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_many(b"CMD:on;"),
+    ];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    let mut up2stream_device: Up2Stream<_, _, 4> =
+        Up2Stream::with_buffer(serial.clone(), serial.clone());
 
-    assert_eq!(response.as_str(), "on");
+    let response = up2stream_device.send_query("CMD");
+    assert!(matches!(response, Err(Error::BufferOverflow)));
+
+    // The driver can still be used after the overflow
+    let response = up2stream_device.send_query("CMD").unwrap();
+    assert_eq!(response, "on");
 
     serial.done();
 }
@@ -55,11 +225,31 @@ fn send_query_rx_with_noise_at_begining() {
     ];
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
+
+    let response = up2stream_device.send_query("CMD").unwrap();
+
+    assert_eq!(response, "on");
+
+    serial.done();
+}
+
+#[test]
+fn send_query_resynchronises_on_partial_command_name() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"CMD;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_many(b"\xff# CXMD:off;"), // Noise
+        SerialTransaction::read_many(b"CMD:on;"),
+    ];
+    let mut serial = SerialMock::new(&expectations);
+
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.send_query("CMD").unwrap();
 
-    assert_eq!(response.as_str(), "on");
+    assert_eq!(response, "on");
 
     serial.done();
 }
@@ -81,11 +271,11 @@ fn send_query_slow_response() {
     ];
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.send_query("CMD").unwrap();
 
-    assert_eq!(response.as_str(), "on");
+    assert_eq!(response, "on");
 
     serial.done();
 }
@@ -103,11 +293,11 @@ fn send_query_noise_at_end() {
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.send_query("CMD").unwrap();
 
-    assert_eq!(response.as_str(), "on");
+    assert_eq!(response, "on");
 
     // Mop up the noise
     serial.read().unwrap();
@@ -127,11 +317,11 @@ fn send_query_rx_parameter_list() {
     ];
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.send_query("CMD").unwrap();
 
-    assert_eq!(response.as_str(), "BT,1,456,PARA");
+    assert_eq!(response, "BT,1,456,PARA");
 
     serial.done();
 }
@@ -147,7 +337,7 @@ fn firmware_version() {
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.firmware_version().unwrap();
 
@@ -180,7 +370,7 @@ fn device_status() -> Result<(), Error> {
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let device_status = up2stream_device.status().unwrap();
 
@@ -191,6 +381,27 @@ fn device_status() -> Result<(), Error> {
     Ok(())
 }
 
+#[test]
+fn device_status_too_few_fields() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"STA;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_many(b"STA:BT,0,50;"),
+    ];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
+
+    assert!(matches!(
+        up2stream_device.status(),
+        Err(Error::IllFormedReponse)
+    ));
+
+    serial.done();
+}
+
 #[test]
 fn system_control() {
     let mut buf: [u8; 10] = [0; 10];
@@ -204,11 +415,11 @@ fn system_control() {
     assert_eq!(SystemControl::Reset.to_parameter_str(&mut buf), b"RESET");
 
     // Device API version 4 functionality
-    // buf = [0; 10];
-    // assert_eq!(
-    //     SystemControl::Recover.to_parameter_str(&mut buf),
-    //     b"RECOVER"
-    // );
+    buf = [0; 10];
+    assert_eq!(
+        SystemControl::Recover.to_parameter_str(&mut buf),
+        b"RECOVER"
+    );
 }
 
 #[test]
@@ -216,11 +427,12 @@ fn execute_system_control() {
     let expectations = [
         SerialTransaction::write(b';'),
         SerialTransaction::write_many(b"SYS:RESET;"),
+        SerialTransaction::flush(),
     ];
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.execute_system_control(SystemControl::Reset);
 
@@ -230,6 +442,7 @@ fn execute_system_control() {
 }
 
 #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn internet_connection() {
     let expectations = [
         SerialTransaction::write(b';'),
@@ -240,7 +453,7 @@ fn internet_connection() {
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.internet_connection();
 
@@ -263,7 +476,7 @@ fn audio_out() {
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.audio_out();
 
@@ -286,7 +499,7 @@ fn audio_out_err() {
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.audio_out();
 
@@ -299,11 +512,12 @@ fn set_audio_out() {
     let expectations = [
         SerialTransaction::write(b';'),
         SerialTransaction::write_many(b"AUD:1;"),
+        SerialTransaction::flush(),
     ];
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.set_audio_out(true);
 
@@ -323,7 +537,7 @@ fn input_source() {
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.input_source();
 
@@ -341,11 +555,12 @@ fn select_input_source() {
     let expectations = [
         SerialTransaction::write(b';'),
         SerialTransaction::write_many(b"SRC:COAX;"),
+        SerialTransaction::flush(),
     ];
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.select_input_source(Source::Coax);
 
@@ -361,16 +576,17 @@ fn volume_doc_code_test() -> Result<(), Error> {
     let initial_expectations = [
         SerialTransaction::write(b';'),
         SerialTransaction::write_many(b"VOL:50;"),
+        SerialTransaction::flush(),
         SerialTransaction::write_many(b"VOL;"),
         SerialTransaction::flush(),
         SerialTransaction::read_many(b"VOL:50;"),
         SerialTransaction::write_many(b"VOL:49;"),
-        //SerialTransaction::flush(),
+        SerialTransaction::flush(),
     ];
 
     let mut serial = SerialMock::new(&initial_expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     // Set the initial volume
     let initial_vol = Volume::new(50)?;
@@ -401,7 +617,7 @@ fn volume() {
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.volume();
 
@@ -417,11 +633,12 @@ fn set_volume() {
     let expectations = [
         SerialTransaction::write(b';'),
         SerialTransaction::write_many(b"VOL:34;"),
+        SerialTransaction::flush(),
     ];
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.set_volume(Volume::new(34).unwrap());
 
@@ -441,7 +658,7 @@ fn mute_status() {
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.mute_status().unwrap();
 
@@ -455,11 +672,12 @@ fn set_mute() {
     let expectations = [
         SerialTransaction::write(b';'),
         SerialTransaction::write_many(b"MUT:1;"),
+        SerialTransaction::flush(),
     ];
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.set_mute(Switch::On);
 
@@ -473,11 +691,12 @@ fn toogle_mute() {
     let expectations = [
         SerialTransaction::write(b';'),
         SerialTransaction::write_many(b"MUT:T;"),
+        SerialTransaction::flush(),
     ];
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.set_mute(Switch::Toggle);
 
@@ -497,7 +716,7 @@ fn treble() {
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.treble();
 
@@ -513,11 +732,12 @@ fn set_treble() {
     let expectations = [
         SerialTransaction::write(b';'),
         SerialTransaction::write_many(b"TRE:-6;"),
+        SerialTransaction::flush(),
     ];
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.set_treble(Treble::new(-6).unwrap());
 
@@ -531,11 +751,12 @@ fn toogle_play_pause() {
     let expectations = [
         SerialTransaction::write(b';'),
         SerialTransaction::write_many(b"POP;"),
+        SerialTransaction::flush(),
     ];
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.play_pause_toggle();
 
@@ -552,11 +773,12 @@ fn stop() {
         SerialTransaction::flush(),
         SerialTransaction::read_many(b"SRC:NET;"),
         SerialTransaction::write_many(b"STP;"),
+        SerialTransaction::flush(),
     ];
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.stop();
 
@@ -566,6 +788,7 @@ fn stop() {
 }
 
 #[test]
+#[allow(clippy::assertions_on_constants)]
 fn stop_err() {
     let expectations = [
         SerialTransaction::write(b';'),
@@ -576,7 +799,7 @@ fn stop_err() {
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.stop();
 
@@ -600,11 +823,12 @@ fn next() {
         SerialTransaction::flush(),
         SerialTransaction::read_many(b"SRC:BT;"),
         SerialTransaction::write_many(b"NXT;"),
+        SerialTransaction::flush(),
     ];
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.next_track();
 
@@ -614,6 +838,7 @@ fn next() {
 }
 
 #[test]
+#[allow(clippy::assertions_on_constants)]
 fn next_err() {
     let expectations = [
         SerialTransaction::write(b';'),
@@ -624,7 +849,7 @@ fn next_err() {
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.next_track();
 
@@ -648,11 +873,12 @@ fn previous() {
         SerialTransaction::flush(),
         SerialTransaction::read_many(b"SRC:BT;"),
         SerialTransaction::write_many(b"PRE;"),
+        SerialTransaction::flush(),
     ];
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let response = up2stream_device.previous_track();
 
@@ -675,7 +901,7 @@ fn bluetooth_connected() {
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     let connected_status = up2stream_device.bluetooth_connected().unwrap();
 
@@ -692,11 +918,12 @@ fn connect_bluetooth() {
         SerialTransaction::flush(),
         SerialTransaction::read_many(b"SRC:BT;"),
         SerialTransaction::write_many(b"BTC:1;"),
+        SerialTransaction::flush(),
     ];
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     up2stream_device.connect_bluetooth().unwrap();
 
@@ -711,13 +938,509 @@ fn disconnect_bluetooth() {
         SerialTransaction::flush(),
         SerialTransaction::read_many(b"SRC:BT;"),
         SerialTransaction::write_many(b"BTC:0;"),
+        SerialTransaction::flush(),
     ];
 
     let mut serial = SerialMock::new(&expectations);
 
-    let mut up2stream_device = Up2Stream::new(serial.clone());
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
 
     up2stream_device.disconnect_bluetooth().unwrap();
 
     serial.done();
 }
+
+#[test]
+fn builder_default() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"VOL;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_many(b"VOL:50;"),
+    ];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    let mut up2stream_device = Up2StreamBuilder::new()
+        .build(serial.clone(), serial.clone())
+        .unwrap();
+
+    assert_eq!(up2stream_device.config(), &Config::default());
+    assert_eq!(up2stream_device.volume().unwrap(), Volume::new(50).unwrap());
+
+    serial.done();
+}
+
+#[test]
+fn builder_settings() {
+    let expectations = [];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    let up2stream_device: Up2Stream<_, _, 16> = Up2StreamBuilder::new()
+        .wake_up(false)
+        .timeout_polls(10)
+        .retries(1)
+        .protocol_version(ProtocolVersion::V4)
+        .response_buffer::<16>()
+        .build(serial.clone(), serial.clone())
+        .unwrap();
+
+    let expected_config = Config {
+        timeout_polls: Some(10),
+        timeout_ms: None,
+        retries: 1,
+        protocol_version: ProtocolVersion::V4,
+    };
+    assert_eq!(up2stream_device.config(), &expected_config);
+
+    serial.done();
+}
+
+#[test]
+fn protocol_version_gates_commands() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"SYS:RECOVER;"),
+        SerialTransaction::flush(),
+    ];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    // Version 3 by default, nothing is sent
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
+    assert!(matches!(
+        up2stream_device.execute_system_control(SystemControl::Recover),
+        Err(Error::NotSupportedForProtocolVersion)
+    ));
+
+    let mut up2stream_device = Up2StreamBuilder::new()
+        .protocol_version(ProtocolVersion::V4)
+        .build(serial.clone(), serial.clone())
+        .unwrap();
+    up2stream_device
+        .execute_system_control(SystemControl::Recover)
+        .unwrap();
+
+    serial.done();
+}
+
+#[test]
+fn builder_check_ready() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"VER;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_many(b"VER:1234-13-42;"),
+    ];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    let result = Up2StreamBuilder::new()
+        .check_ready(true)
+        .build(serial.clone(), serial.clone());
+
+    assert!(result.is_ok());
+
+    serial.done();
+}
+
+#[test]
+fn builder_check_ready_no_response() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"VER;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_error(nb::Error::WouldBlock),
+        SerialTransaction::read_error(nb::Error::WouldBlock),
+        SerialTransaction::read_error(nb::Error::WouldBlock),
+        // Resent
+        SerialTransaction::write_many(b"VER;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_error(nb::Error::WouldBlock),
+        SerialTransaction::read_error(nb::Error::WouldBlock),
+        SerialTransaction::read_error(nb::Error::WouldBlock),
+    ];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    let result = Up2StreamBuilder::new()
+        .timeout_polls(2)
+        .retries(1)
+        .check_ready(true)
+        .build(serial.clone(), serial.clone());
+
+    assert!(matches!(result, Err(Error::Timeout)));
+
+    serial.done();
+}
+
+#[test]
+fn builder_timeout_ms() {
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    // Advances by 10 ms every time it is read
+    static NOW_MS: AtomicU32 = AtomicU32::new(0);
+    fn clock() -> u32 {
+        NOW_MS.fetch_add(10, Ordering::Relaxed)
+    }
+
+    let expectations = [
+        SerialTransaction::write_many(b"VER;"),
+        SerialTransaction::flush(),
+        // Idle at 0, 10 and 20 ms, times out at 30 ms
+        SerialTransaction::read_error(nb::Error::WouldBlock),
+        SerialTransaction::read_error(nb::Error::WouldBlock),
+        SerialTransaction::read_error(nb::Error::WouldBlock),
+        SerialTransaction::read_error(nb::Error::WouldBlock),
+    ];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    // The clock takes precedence over the count of polls
+    let mut up2stream_device = Up2StreamBuilder::new()
+        .wake_up(false)
+        .timeout_polls(1)
+        .timeout_ms(25, clock)
+        .retries(0)
+        .build(serial.clone(), serial.clone())
+        .unwrap();
+
+    assert!(matches!(
+        up2stream_device.firmware_version(),
+        Err(Error::Timeout)
+    ));
+
+    serial.done();
+}
+
+#[test]
+fn send_query_resent_after_ill_formed_response() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"CMD;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_many(b"CMD:o\r"),
+        SerialTransaction::write_many(b"CMD;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_many(b"CMD:on;"),
+    ];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
+
+    let response = up2stream_device.send_query("CMD").unwrap();
+
+    assert_eq!(response, "on");
+
+    serial.done();
+}
+
+#[test]
+fn query_many_out_of_order() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"VOL;"),
+        SerialTransaction::flush(),
+        SerialTransaction::write_many(b"MUT;"),
+        SerialTransaction::flush(),
+        SerialTransaction::write_many(b"SRC;"),
+        SerialTransaction::flush(),
+        // Replies out of order with noise and an unsolicited frame
+        SerialTransaction::read_many(b"SRC:BT;\r\nx\x00MUT:1;PLA:1;VOL:20;"),
+    ];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
+
+    let replies = up2stream_device.query_many(&[Query::Volume, Query::Mute, Query::InputSource]);
+
+    assert_eq!(
+        replies[0].as_ref().unwrap(),
+        &Reply::Volume(Volume::new(20).unwrap())
+    );
+    assert_eq!(replies[1].as_ref().unwrap(), &Reply::Mute(true));
+    assert_eq!(
+        replies[2].as_ref().unwrap(),
+        &Reply::InputSource(Source::Bluetooth)
+    );
+
+    serial.done();
+}
+
+#[test]
+fn query_many_same_query_sent_once() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"BAS;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_many(b"BAS:-4;"),
+    ];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
+
+    let [first, second] = up2stream_device.query_many(&[Query::Bass, Query::Bass]);
+
+    assert_eq!(first.unwrap(), Reply::Bass(Bass::new(-4).unwrap()));
+    assert_eq!(second.unwrap(), Reply::Bass(Bass::new(-4).unwrap()));
+
+    serial.done();
+}
+
+#[test]
+fn query_many_resends_missing_replies() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"TRE;"),
+        SerialTransaction::flush(),
+        SerialTransaction::write_many(b"STA;"),
+        SerialTransaction::flush(),
+        SerialTransaction::write_many(b"AUD;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_many(b"AUD:1;TRE:-1;"),
+        SerialTransaction::read_error(nb::Error::WouldBlock),
+        // Only the query without a reply is resent
+        SerialTransaction::write_many(b"STA;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_many(b"STA:NET,0,30,-2,4,1,1,1,1,0;"),
+    ];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    let mut up2stream_device = Up2StreamBuilder::new()
+        .timeout_polls(0)
+        .retries(1)
+        .build(serial.clone(), serial.clone())
+        .unwrap();
+
+    let [treble, status, audio_out] =
+        up2stream_device.query_many(&[Query::Treble, Query::Status, Query::AudioOut]);
+
+    assert_eq!(treble.unwrap(), Reply::Treble(Treble::new(-1).unwrap()));
+    assert!(
+        matches!(status, Ok(Reply::Status(DeviceStatus { volume, .. })) if volume == Volume::new(30).unwrap())
+    );
+    assert_eq!(audio_out.unwrap(), Reply::AudioOut(true));
+
+    serial.done();
+}
+
+#[test]
+fn query_many_missing_reply_times_out() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"WWW;"),
+        SerialTransaction::flush(),
+        SerialTransaction::write_many(b"VOL;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_many(b"VOL:abc;"),
+        SerialTransaction::read_error(nb::Error::WouldBlock),
+    ];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    let mut up2stream_device = Up2StreamBuilder::new()
+        .timeout_polls(0)
+        .retries(0)
+        .build(serial.clone(), serial.clone())
+        .unwrap();
+
+    let [internet, volume] =
+        up2stream_device.query_many(&[Query::InternetConnection, Query::Volume]);
+
+    assert!(matches!(internet, Err(Error::Timeout)));
+    assert!(volume.is_err());
+
+    serial.done();
+}
+
+#[test]
+fn query_many_dropped_reply_times_out_by_default() {
+    // Replies only to the first query, then never has data again
+    struct Board(&'static [u8]);
+
+    impl Read<u8> for Board {
+        type Error = ();
+
+        fn read(&mut self) -> nb::Result<u8, Self::Error> {
+            let (&byte, rest) = self.0.split_first().ok_or(nb::Error::WouldBlock)?;
+            self.0 = rest;
+            Ok(byte)
+        }
+    }
+
+    impl Write<u8> for Board {
+        type Error = ();
+
+        fn write(&mut self, _word: u8) -> nb::Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    // No timeout is configured
+    let mut up2stream_device = Up2Stream::new_combined(Board(b"VOL:30;"));
+
+    let [volume, mute] = up2stream_device.query_many(&[Query::Volume, Query::Mute]);
+
+    assert_eq!(volume.unwrap(), Reply::Volume(Volume::new(30).unwrap()));
+    assert!(matches!(mute, Err(Error::Timeout)));
+}
+
+#[test]
+fn send_commands_as_data() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"SRC:USB;"),
+        SerialTransaction::flush(),
+        SerialTransaction::write_many(b"MUT:0;"),
+        SerialTransaction::flush(),
+        SerialTransaction::write_many(b"NXT;"),
+        SerialTransaction::flush(),
+    ];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
+
+    let queue = [
+        Command::SelectSource(Source::Usb),
+        Command::SetMute(Switch::Off),
+        Command::NextTrack,
+    ];
+
+    for command in &queue {
+        up2stream_device.send(command).unwrap();
+    }
+
+    serial.done();
+}
+
+#[test]
+fn raw_command() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"WRS;"),
+        SerialTransaction::flush(),
+        SerialTransaction::write_many(b"NAM:Living room;"),
+        SerialTransaction::flush(),
+        SerialTransaction::write_many(b"XYZ:1,-2,a.b;"),
+        SerialTransaction::flush(),
+    ];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
+
+    up2stream_device.raw_command("WRS", &[]).unwrap();
+    up2stream_device
+        .raw_command("NAM", &["Living room"])
+        .unwrap();
+    up2stream_device
+        .raw_command("XYZ", &["1", "-2", "a.b"])
+        .unwrap();
+
+    serial.done();
+}
+
+#[test]
+fn raw_command_invalid() {
+    // Nothing is sent
+    let mut serial = SerialMock::new(&[SerialTransaction::write(b';')]);
+
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
+
+    for (name, parameters) in [
+        ("", &[][..]),
+        ("VOL;", &[]),
+        ("V L", &[]),
+        ("VOL", &["1;VOL:100"]),
+        ("VOL", &["1,2"]),
+        ("VOL", &["1:2"]),
+        ("VOL", &[""]),
+        ("VOL", &["\r\n"]),
+        ("NAM", &["Küche"]),
+    ] {
+        assert!(
+            matches!(
+                up2stream_device.raw_command(name, parameters),
+                Err(Error::InvalidString)
+            ),
+            "{} {:?}",
+            name,
+            parameters
+        );
+    }
+
+    assert!(matches!(
+        up2stream_device.raw_query("ST;A"),
+        Err(Error::InvalidString)
+    ));
+
+    serial.done();
+}
+
+#[test]
+fn raw_query() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"STA;"),
+        SerialTransaction::flush(),
+        // Noise is skipped as for other queries
+        SerialTransaction::read_many(b"\r\nSTA:BT,0,50,-4,4,1,1,1,0,0,3;"),
+    ];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
+
+    let response = up2stream_device.raw_query("STA").unwrap();
+
+    assert_eq!(response.as_str(), "BT,0,50,-4,4,1,1,1,0,0,3");
+    assert_eq!(response.parameters().count(), 11);
+    assert_eq!(response.parse::<Source>(0).unwrap(), Source::Bluetooth);
+    assert_eq!(
+        response.parse::<Volume>(2).unwrap(),
+        Volume::new(50).unwrap()
+    );
+    assert!(matches!(
+        response.parse::<Volume>(11),
+        Err(Error::IllFormedReponse)
+    ));
+
+    serial.done();
+}
+
+#[test]
+fn raw_query_printable_parameters() {
+    let expectations = [
+        SerialTransaction::write(b';'),
+        SerialTransaction::write_many(b"NAM;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_many(b"NAM:Living room;"),
+        SerialTransaction::write_many(b"XYZ;"),
+        SerialTransaction::flush(),
+        SerialTransaction::read_many(b"XYZ:a b:c,(d)!?;"),
+    ];
+
+    let mut serial = SerialMock::new(&expectations);
+
+    let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
+
+    let response = up2stream_device.raw_query("NAM").unwrap();
+    assert_eq!(response.as_str(), "Living room");
+
+    let response = up2stream_device.raw_query("XYZ").unwrap();
+    assert_eq!(response.as_str(), "a b:c,(d)!?");
+    assert_eq!(response.parameters().nth(1), Some("(d)!?"));
+
+    serial.done();
+}
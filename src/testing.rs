@@ -0,0 +1,272 @@
+//! Helpers for writing tests of code using the driver with `embedded-hal-mock`.
+//!
+//! The functions in this module generate the serial transactions expected by
+//! `embedded_hal_mock::serial::Mock` when the driver sends commands and queries,
+//! so that the frames of the UART API do not have to be written out by hand.
+//! [Expectations] collects these transactions for a whole test.
+//!
+//! [MemoryFlash] stands in for the NOR flash used by [SettingsStore](crate::SettingsStore).
+//!
+//! This module is only available with the `testing` feature.
+//!
+//! # Example
+//! ```
+//! use up2stream_uart::{Up2Stream, Source, Volume};
+//! use up2stream_uart::testing::Expectations;
+//!
+//! let mut serial = Expectations::new()
+//!     .handshake()
+//!     .query("VOL", "50")
+//!     .command("SRC", "BT")
+//!     .mock();
+//!
+//! let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
+//!
+//! assert_eq!(up2stream_device.volume().unwrap(), Volume::new(50).unwrap());
+//! up2stream_device.select_input_source(Source::Bluetooth).unwrap();
+//!
+//! serial.done();
+//! ```
+
+extern crate std;
+
+use std::vec;
+use std::vec::Vec;
+
+use embedded_hal_mock::serial::{Mock, Transaction};
+use embedded_storage::nor_flash::{
+    check_erase, check_read, check_write, ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash,
+};
+
+use crate::{PARAMETER_START, TERMINATOR};
+
+/// The transactions of the wake-up character `;` sent when the driver is created.
+pub fn handshake() -> Vec<Transaction<u8>> {
+    vec![Transaction::write(TERMINATOR)]
+}
+
+/// The transactions of the query `command` answered by the board with `response`.
+///
+/// For example `expect_query("VOL", "50")` expects `VOL;` to be written and then reads `VOL:50;`.
+pub fn expect_query(command: &str, response: &str) -> Vec<Transaction<u8>> {
+    let mut query = Vec::from(command.as_bytes());
+    query.push(TERMINATOR);
+
+    vec![
+        Transaction::write_many(query),
+        Transaction::flush(),
+        Transaction::read_many(frame(command, response)),
+    ]
+}
+
+/// The transactions of the command `command` with the parameter `parameter`.
+///
+/// For example `expect_command("SRC", "BT")` expects `SRC:BT;` to be written.
+/// Commands without a parameter, such as `POP`, use an empty string.
+pub fn expect_command(command: &str, parameter: &str) -> Vec<Transaction<u8>> {
+    vec![
+        Transaction::write_many(frame(command, parameter)),
+        Transaction::flush(),
+    ]
+}
+
+fn frame(command: &str, parameter: &str) -> Vec<u8> {
+    let mut frame = Vec::from(command.as_bytes());
+
+    if !parameter.is_empty() {
+        frame.push(PARAMETER_START);
+        frame.extend_from_slice(parameter.as_bytes());
+    }
+
+    frame.push(TERMINATOR);
+    frame
+}
+
+/// Collects the transactions expected during a test.
+#[derive(Default)]
+pub struct Expectations {
+    transactions: Vec<Transaction<u8>>,
+}
+
+impl Expectations {
+    /// Create an empty list of expectations.
+    pub fn new() -> Self {
+        Expectations {
+            transactions: Vec::new(),
+        }
+    }
+
+    /// Expect the wake-up character, see [handshake].
+    pub fn handshake(self) -> Self {
+        self.extend(handshake())
+    }
+
+    /// Expect a query answered with `response`, see [expect_query].
+    pub fn query(self, command: &str, response: &str) -> Self {
+        self.extend(expect_query(command, response))
+    }
+
+    /// Expect a command, see [expect_command].
+    pub fn command(self, command: &str, parameter: &str) -> Self {
+        self.extend(expect_command(command, parameter))
+    }
+
+    /// Expect a single transaction, for instance a read or write error.
+    pub fn transaction(mut self, transaction: Transaction<u8>) -> Self {
+        self.transactions.push(transaction);
+        self
+    }
+
+    /// Expect all the `transactions` in order.
+    pub fn extend(mut self, transactions: impl IntoIterator<Item = Transaction<u8>>) -> Self {
+        self.transactions.extend(transactions);
+        self
+    }
+
+    /// The collected transactions.
+    pub fn build(self) -> Vec<Transaction<u8>> {
+        self.transactions
+    }
+
+    /// Create a mock UART with the collected transactions.
+    pub fn mock(self) -> Mock<u8> {
+        Mock::new(&self.transactions)
+    }
+}
+
+/// NOR flash of `SIZE` bytes kept in memory.
+///
+/// Like real NOR flash, erasing sets the bytes to `0xFF` and writing can only clear bits.
+/// Reads, writes and erases must be aligned to [ReadNorFlash::READ_SIZE], [NorFlash::WRITE_SIZE]
+/// and [NorFlash::ERASE_SIZE].
+pub struct MemoryFlash<const SIZE: usize> {
+    data: [u8; SIZE],
+}
+
+impl<const SIZE: usize> MemoryFlash<SIZE> {
+    /// Create an erased flash.
+    pub fn new() -> Self {
+        MemoryFlash { data: [0xFF; SIZE] }
+    }
+
+    /// The content of the flash.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl<const SIZE: usize> Default for MemoryFlash<SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SIZE: usize> ErrorType for MemoryFlash<SIZE> {
+    type Error = NorFlashErrorKind;
+}
+
+impl<const SIZE: usize> ReadNorFlash for MemoryFlash<SIZE> {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        check_read(self, offset, bytes.len())?;
+
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        SIZE
+    }
+}
+
+impl<const SIZE: usize> NorFlash for MemoryFlash<SIZE> {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = 256;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        check_erase(self, from, to)?;
+
+        self.data[from as usize..to as usize].fill(0xFF);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check_write(self, offset, bytes.len())?;
+
+        let offset = offset as usize;
+        for (stored, byte) in self.data[offset..].iter_mut().zip(bytes) {
+            *stored &= byte;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Bass, Source, Switch, Up2Stream, Up2StreamBuilder};
+
+    #[test]
+    fn query() {
+        let mut serial = Expectations::new().handshake().query("BAS", "-3").mock();
+
+        let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
+
+        assert_eq!(up2stream_device.bass().unwrap(), Bass::new(-3).unwrap());
+
+        serial.done();
+    }
+
+    #[test]
+    fn command() {
+        let mut serial = Expectations::new()
+            .handshake()
+            .command("SRC", "LINE-IN")
+            .command("POP", "")
+            .mock();
+
+        let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
+
+        up2stream_device
+            .select_input_source(Source::LineIn)
+            .unwrap();
+        up2stream_device.play_pause_toggle().unwrap();
+
+        serial.done();
+    }
+
+    #[test]
+    fn without_handshake() {
+        let mut serial = Expectations::new().query("VER", "1234-13-42").mock();
+
+        let mut up2stream_device = Up2StreamBuilder::new()
+            .wake_up(false)
+            .build(serial.clone(), serial.clone())
+            .unwrap();
+
+        assert_eq!(up2stream_device.firmware_version().unwrap(), "1234-13-42");
+
+        serial.done();
+    }
+
+    #[test]
+    fn build() {
+        let transactions = Expectations::new()
+            .extend(handshake())
+            .extend(expect_query("MUT", "1"))
+            .transaction(Transaction::write_many(b"MUT:0;"))
+            .transaction(Transaction::flush())
+            .build();
+
+        let mut serial = Mock::new(&transactions);
+
+        let mut up2stream_device = Up2Stream::new(serial.clone(), serial.clone());
+
+        assert!(up2stream_device.mute_status().unwrap());
+        up2stream_device.set_mute(Switch::Off).unwrap();
+
+        serial.done();
+    }
+}
@@ -0,0 +1,963 @@
+//! Recording and replay of the serial traffic between the driver and the board.
+//!
+//! A [Recorder] wraps a UART implementing the `embedded_hal::serial::{Read, Write}` traits and
+//! logs every byte that is transmitted (TX) or received (RX), together with the time from a [Clock].
+//! The bytes are grouped into [Record]s, which end after each `;` terminated frame or when the
+//! direction changes, and are written to a [Log]:
+//!
+//! * [TextLog] writes one line per record, e.g. `1520 RX VOL:50;`. Bytes that are not printable are
+//!   escaped as `\xNN` and a backslash as `\\`. Empty lines and lines starting with `#` are ignored
+//!   when the text is parsed with [parse_text].
+//! * [BinaryLog] writes a compact encoding into a byte buffer, e.g. for dumping to flash on a
+//!   device in the field. It is decoded with [decode_binary].
+//!
+//! A [Replay] feeds a recorded session back to the driver. The bytes sent by the driver are compared
+//! with the recorded TX bytes and the first difference is reported as a [Divergence].
+//!
+//! # Example
+//! ```
+//! use up2stream_uart::{Up2Stream, Volume};
+//! use up2stream_uart::transcript::{parse_text, Replay};
+//!
+//! let transcript = "\
+//! ## Wake-up character and query of the volume
+//! 0 TX ;
+//! 10 TX VOL;
+//! 12 RX VOL:50;
+//! ";
+//!
+//! let mut up2stream_device = Up2Stream::new_combined(Replay::new(parse_text(transcript)));
+//!
+//! assert_eq!(up2stream_device.volume().unwrap(), Volume::new(50).unwrap());
+//!
+//! let (replay, _) = up2stream_device.release();
+//! assert!(replay.finish().is_ok());
+//! ```
+
+use core::convert::Infallible;
+use core::fmt;
+use core::str::{FromStr, Lines};
+
+use arrayvec::ArrayVec;
+use embedded_hal::serial::{Read, Write};
+
+use crate::clock::Clock;
+use crate::error::Error;
+use crate::TERMINATOR;
+
+/// The maximum number of bytes in a [Record].
+pub const MAX_RECORD_LENGTH: usize = 128;
+
+// Header bit of the binary encoding set for received bytes
+const BINARY_RX: u8 = 0x80;
+
+// Header byte, the time as LEB128 (at most 5 bytes for an u32) and the bytes
+const MAX_BINARY_RECORD_LENGTH: usize = 1 + 5 + MAX_RECORD_LENGTH;
+
+/// The direction of the bytes of a [Record], as seen from the driver.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Direction {
+    /// Transmitted to the board
+    Tx,
+    /// Received from the board
+    Rx,
+}
+
+impl Direction {
+    fn to_str(self) -> &'static str {
+        match self {
+            Self::Tx => "TX",
+            Self::Rx => "RX",
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Direction, Self::Err> {
+        match input {
+            "TX" => Ok(Direction::Tx),
+            "RX" => Ok(Direction::Rx),
+            _ => Err(Error::InvalidTranscript),
+        }
+    }
+}
+
+/// Consecutive bytes sent in one direction.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Record {
+    /// The time in milliseconds at which the first byte was sent.
+    pub time_ms: u32,
+    /// The direction the bytes were sent in.
+    pub direction: Direction,
+    /// The bytes, at least one and at most [MAX_RECORD_LENGTH].
+    pub bytes: ArrayVec<u8, MAX_RECORD_LENGTH>,
+}
+
+impl Record {
+    /// Create a record, returning an error if `bytes` is empty or longer than [MAX_RECORD_LENGTH].
+    pub fn new(time_ms: u32, direction: Direction, bytes: &[u8]) -> Result<Record, Error> {
+        if bytes.is_empty() {
+            return Err(Error::InvalidTranscript);
+        }
+
+        Ok(Record {
+            time_ms,
+            direction,
+            bytes: ArrayVec::try_from(bytes).map_err(|_| Error::BufferOverflow)?,
+        })
+    }
+}
+
+/// Formats the record as a line of the text format without the line ending.
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} ", self.time_ms, self.direction.to_str())?;
+
+        for byte in &self.bytes {
+            match byte {
+                b'\\' => f.write_str("\\\\")?,
+                b' '..=b'~' => write!(f, "{}", *byte as char)?,
+                _ => write!(f, "\\x{:02X}", byte)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The destination of the records of a [Recorder].
+pub trait Log {
+    /// Store the record.
+    fn record(&mut self, record: &Record) -> Result<(), Error>;
+}
+
+/// Writes records in the line-based text format, e.g. to a `String` or a `heapless::String`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TextLog<W>(pub W);
+
+impl<W> Log for TextLog<W>
+where
+    W: fmt::Write,
+{
+    fn record(&mut self, record: &Record) -> Result<(), Error> {
+        writeln!(self.0, "{}", record).map_err(|_| Error::Write)
+    }
+}
+
+/// Writes records in the compact binary format into a buffer.
+///
+/// Each record is encoded as a header byte holding the direction (bit 7 set for RX) and the number
+/// of bytes less one, the milliseconds since the previous record as a LEB128 varint, and the bytes.
+#[derive(Debug)]
+pub struct BinaryLog<'a> {
+    buffer: &'a mut [u8],
+    length: usize,
+    last_time_ms: u32,
+}
+
+impl<'a> BinaryLog<'a> {
+    /// Create a log writing into `buffer`.
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        BinaryLog {
+            buffer,
+            length: 0,
+            last_time_ms: 0,
+        }
+    }
+
+    /// The encoded records.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.length]
+    }
+}
+
+impl Log for BinaryLog<'_> {
+    /// Returns [Error::BufferOverflow] if the record does not fit into the remaining buffer and
+    /// [Error::InvalidTranscript] if it has no bytes, which the encoding cannot represent.
+    fn record(&mut self, record: &Record) -> Result<(), Error> {
+        if record.bytes.is_empty() {
+            return Err(Error::InvalidTranscript);
+        }
+
+        let mut encoded = ArrayVec::<u8, MAX_BINARY_RECORD_LENGTH>::new();
+
+        let direction = match record.direction {
+            Direction::Tx => 0,
+            Direction::Rx => BINARY_RX,
+        };
+        encoded.push(direction | (record.bytes.len() - 1) as u8);
+
+        let mut delta = record.time_ms.wrapping_sub(self.last_time_ms);
+        loop {
+            let byte = (delta & 0x7F) as u8;
+            delta >>= 7;
+            if delta == 0 {
+                encoded.push(byte);
+                break;
+            }
+            encoded.push(byte | 0x80);
+        }
+
+        encoded.extend(record.bytes.iter().copied());
+
+        let end = self.length + encoded.len();
+        if end > self.buffer.len() {
+            return Err(Error::BufferOverflow);
+        }
+
+        self.buffer[self.length..end].copy_from_slice(&encoded);
+        self.length = end;
+        self.last_time_ms = record.time_ms;
+
+        Ok(())
+    }
+}
+
+/// Parse the records of a transcript in the text format, see [TextLog].
+pub fn parse_text(text: &str) -> TextRecords<'_> {
+    TextRecords {
+        lines: text.lines(),
+    }
+}
+
+/// Iterator over the records of a transcript in the text format, created by [parse_text].
+#[derive(Debug, Clone)]
+pub struct TextRecords<'a> {
+    lines: Lines<'a>,
+}
+
+impl Iterator for TextRecords<'_> {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for line in self.lines.by_ref() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            return Some(parse_line(line));
+        }
+        None
+    }
+}
+
+fn parse_line(line: &str) -> Result<Record, Error> {
+    let mut fields = line.splitn(3, ' ');
+
+    let time_ms = fields
+        .next()
+        .and_then(|time| time.parse().ok())
+        .ok_or(Error::InvalidTranscript)?;
+    let direction = fields.next().ok_or(Error::InvalidTranscript)?.parse()?;
+    let text = fields.next().ok_or(Error::InvalidTranscript)?;
+
+    let mut bytes = ArrayVec::<u8, MAX_RECORD_LENGTH>::new();
+    let mut chars = text.bytes();
+    while let Some(byte) = chars.next() {
+        let byte = match byte {
+            b'\\' => match chars.next() {
+                Some(b'\\') => b'\\',
+                Some(b'x') => {
+                    let digits = [
+                        chars.next().ok_or(Error::InvalidTranscript)?,
+                        chars.next().ok_or(Error::InvalidTranscript)?,
+                    ];
+                    core::str::from_utf8(&digits)
+                        .ok()
+                        .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                        .ok_or(Error::InvalidTranscript)?
+                }
+                _ => return Err(Error::InvalidTranscript),
+            },
+            _ => byte,
+        };
+        bytes.try_push(byte).map_err(|_| Error::BufferOverflow)?;
+    }
+
+    Record::new(time_ms, direction, &bytes)
+}
+
+/// Decode the records of a transcript in the binary format, see [BinaryLog].
+pub fn decode_binary(bytes: &[u8]) -> BinaryRecords<'_> {
+    BinaryRecords { bytes, time_ms: 0 }
+}
+
+/// Iterator over the records of a transcript in the binary format, created by [decode_binary].
+///
+/// The iteration ends after the first record that cannot be decoded.
+#[derive(Debug, Clone)]
+pub struct BinaryRecords<'a> {
+    bytes: &'a [u8],
+    time_ms: u32,
+}
+
+impl BinaryRecords<'_> {
+    fn decode(&mut self) -> Result<Record, Error> {
+        let (header, mut rest) = self.bytes.split_first().ok_or(Error::InvalidTranscript)?;
+
+        let mut delta: u32 = 0;
+        for shift in (0..35).step_by(7) {
+            let (byte, remaining) = rest.split_first().ok_or(Error::InvalidTranscript)?;
+            rest = remaining;
+            delta |= ((byte & 0x7F) as u32) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            } else if shift == 28 {
+                return Err(Error::InvalidTranscript);
+            }
+        }
+
+        let length = (header & !BINARY_RX) as usize + 1;
+        if rest.len() < length {
+            return Err(Error::InvalidTranscript);
+        }
+
+        let direction = if header & BINARY_RX == 0 {
+            Direction::Tx
+        } else {
+            Direction::Rx
+        };
+
+        self.time_ms = self.time_ms.wrapping_add(delta);
+        self.bytes = &rest[length..];
+
+        Record::new(self.time_ms, direction, &rest[..length])
+    }
+}
+
+impl Iterator for BinaryRecords<'_> {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+
+        let record = self.decode();
+        if record.is_err() {
+            self.bytes = &[];
+        }
+        Some(record)
+    }
+}
+
+/// Wraps a UART and records all the bytes transmitted and received into a [Log].
+///
+/// The recorder implements the `embedded_hal::serial::{Read, Write}` traits and is passed to
+/// [Up2Stream::new_combined](crate::Up2Stream::new_combined) instead of the UART. Recording never
+/// interferes with the communication, records that cannot be stored are counted instead, see
+/// [Recorder::lost_records].
+///
+/// # Example
+/// ```
+/// use up2stream_uart::Up2Stream;
+/// use up2stream_uart::transcript::{BinaryLog, Recorder};
+/// # use embedded_hal_mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+/// # let mut uart = SerialMock::new(&[
+/// #     SerialTransaction::write_many(b";VOL;"),
+/// #     SerialTransaction::flush(),
+/// #     SerialTransaction::read_many(b"VOL:50;"),
+/// # ]);
+/// # let mut timer = 0;
+///
+/// let mut flash_page = [0; 256];
+/// let clock = || { timer += 1; timer };
+///
+/// let recorder = Recorder::new(uart, clock, BinaryLog::new(&mut flash_page));
+/// let mut up2stream_device = Up2Stream::new_combined(recorder);
+///
+/// up2stream_device.volume().unwrap();
+///
+/// let (recorder, _) = up2stream_device.release();
+/// let (_uart, log) = recorder.release();
+/// assert!(!log.as_bytes().is_empty());
+/// ```
+#[derive(Debug)]
+pub struct Recorder<UART, C, L> {
+    uart: UART,
+    clock: C,
+    log: L,
+    record: Option<Record>,
+    lost_records: u32,
+}
+
+impl<UART, C, L> Recorder<UART, C, L>
+where
+    C: Clock,
+    L: Log,
+{
+    /// Record the traffic of `uart`, timestamped with `clock`, into `log`.
+    pub fn new(uart: UART, clock: C, log: L) -> Self {
+        Recorder {
+            uart,
+            clock,
+            log,
+            record: None,
+            lost_records: 0,
+        }
+    }
+
+    /// The log, which does not yet contain the record currently being collected,
+    /// see [Recorder::flush_log].
+    pub fn log(&self) -> &L {
+        &self.log
+    }
+
+    /// The number of records that could not be stored in the log.
+    pub fn lost_records(&self) -> u32 {
+        self.lost_records
+    }
+
+    /// Store the record currently being collected in the log.
+    pub fn flush_log(&mut self) {
+        if let Some(record) = self.record.take() {
+            if self.log.record(&record).is_err() {
+                self.lost_records = self.lost_records.saturating_add(1);
+            }
+        }
+    }
+
+    /// Store the current record and give back the UART and the log.
+    pub fn release(mut self) -> (UART, L) {
+        self.flush_log();
+        (self.uart, self.log)
+    }
+
+    fn record_byte(&mut self, direction: Direction, byte: u8) {
+        match &mut self.record {
+            Some(record) if record.direction == direction && !record.bytes.is_full() => {
+                record.bytes.push(byte)
+            }
+            _ => {
+                self.flush_log();
+                let mut bytes = ArrayVec::new();
+                bytes.push(byte);
+                self.record = Some(Record {
+                    time_ms: self.clock.now_ms(),
+                    direction,
+                    bytes,
+                });
+            }
+        }
+
+        // One line per frame
+        if byte == TERMINATOR {
+            self.flush_log();
+        }
+    }
+}
+
+impl<UART, C, L> Read<u8> for Recorder<UART, C, L>
+where
+    UART: Read<u8>,
+    C: Clock,
+    L: Log,
+{
+    type Error = UART::Error;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        let byte = self.uart.read()?;
+        self.record_byte(Direction::Rx, byte);
+        Ok(byte)
+    }
+}
+
+impl<UART, C, L> Write<u8> for Recorder<UART, C, L>
+where
+    UART: Write<u8>,
+    C: Clock,
+    L: Log,
+{
+    type Error = UART::Error;
+
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        self.uart.write(word)?;
+        self.record_byte(Direction::Tx, word);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        self.uart.flush()
+    }
+}
+
+/// A difference between the bytes sent by the driver and a recorded session.
+#[derive(Debug)]
+pub enum Divergence {
+    /// The driver sent `actual` where `expected` was recorded.
+    Sent {
+        /// Index of the record in the transcript
+        record: usize,
+        /// Position of the byte in the record
+        offset: usize,
+        expected: u8,
+        actual: u8,
+    },
+    /// The driver sent a byte while a response was recorded, or after the end of the transcript.
+    Unexpected {
+        /// Index of the record in the transcript, which equals the number of records if the
+        /// end of the transcript was reached
+        record: usize,
+        actual: u8,
+    },
+    /// The driver did not send or receive all the recorded bytes.
+    Incomplete {
+        /// Index of the first record not completely replayed
+        record: usize,
+    },
+    /// The transcript could not be parsed.
+    InvalidTranscript {
+        /// Index of the record in the transcript
+        record: usize,
+        error: Error,
+    },
+}
+
+/// Returned by [Replay] when the driver reads but no recorded response can be returned,
+/// so that the driver does not wait forever.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ReplayError {
+    /// All the recorded bytes have been replayed.
+    EndOfTranscript,
+    /// The driver diverged from the recording and waits for a response that was not recorded.
+    Diverged,
+}
+
+/// Transport replaying a recorded session, see the [module documentation](self).
+///
+/// The recorded RX bytes are returned to the driver once it has sent all the preceding TX bytes.
+/// Timing is not replayed. Any difference in the bytes sent by the driver is recorded as a
+/// [Divergence], the first of which is reported by [Replay::divergence] and [Replay::finish].
+#[derive(Debug)]
+pub struct Replay<I> {
+    records: I,
+    record: Option<Record>,
+    index: usize,
+    offset: usize,
+    ended: bool,
+    divergence: Option<Divergence>,
+}
+
+impl<I> Replay<I>
+where
+    I: Iterator<Item = Result<Record, Error>>,
+{
+    /// Replay the `records`, e.g. from [parse_text] or [decode_binary].
+    pub fn new(records: I) -> Self {
+        Replay {
+            records,
+            record: None,
+            index: 0,
+            offset: 0,
+            ended: false,
+            divergence: None,
+        }
+    }
+
+    /// The first difference found so far.
+    pub fn divergence(&self) -> Option<&Divergence> {
+        self.divergence.as_ref()
+    }
+
+    /// Check that the driver sent exactly the recorded bytes and that the whole session was replayed.
+    pub fn finish(mut self) -> Result<(), Divergence> {
+        if self.current().is_some() {
+            let record = self.index;
+            self.diverge(Divergence::Incomplete { record });
+        }
+
+        match self.divergence {
+            Some(divergence) => Err(divergence),
+            None => Ok(()),
+        }
+    }
+
+    // The record with bytes still to be replayed
+    fn current(&mut self) -> Option<&Record> {
+        let exhausted = match &self.record {
+            Some(record) => self.offset >= record.bytes.len(),
+            None => true,
+        };
+
+        if exhausted && !self.ended {
+            if self.record.take().is_some() {
+                self.index += 1;
+            }
+            self.offset = 0;
+
+            match self.records.next() {
+                Some(Ok(record)) => self.record = Some(record),
+                Some(Err(error)) => {
+                    let record = self.index;
+                    self.diverge(Divergence::InvalidTranscript { record, error });
+                    self.ended = true;
+                }
+                None => self.ended = true,
+            }
+        }
+
+        self.record.as_ref()
+    }
+
+    fn diverge(&mut self, divergence: Divergence) {
+        if self.divergence.is_none() {
+            self.divergence = Some(divergence);
+        }
+    }
+}
+
+impl<I> Read<u8> for Replay<I>
+where
+    I: Iterator<Item = Result<Record, Error>>,
+{
+    type Error = ReplayError;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        let direction = self.current().map(|record| record.direction);
+        match direction {
+            Some(Direction::Rx) => {
+                let byte = self
+                    .record
+                    .as_ref()
+                    .map_or(0, |record| record.bytes[self.offset]);
+                self.offset += 1;
+                Ok(byte)
+            }
+            Some(_) if self.divergence.is_some() => Err(nb::Error::Other(ReplayError::Diverged)),
+            // Waiting for the driver to send the recorded bytes
+            Some(_) => Err(nb::Error::WouldBlock),
+            None => Err(nb::Error::Other(ReplayError::EndOfTranscript)),
+        }
+    }
+}
+
+impl<I> Write<u8> for Replay<I>
+where
+    I: Iterator<Item = Result<Record, Error>>,
+{
+    type Error = Infallible;
+
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        let direction = self.current().map(|record| record.direction);
+        let (offset, record) = (self.offset, self.index);
+        let expected = match (direction, &self.record) {
+            (Some(Direction::Tx), Some(current)) => Some(current.bytes[offset]),
+            _ => None,
+        };
+
+        match expected {
+            Some(expected) => {
+                self.offset += 1;
+                if expected != word {
+                    self.diverge(Divergence::Sent {
+                        record,
+                        offset,
+                        expected,
+                        actual: word,
+                    });
+                }
+            }
+            None => self.diverge(Divergence::Unexpected {
+                record,
+                actual: word,
+            }),
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sim::Simulator;
+    use crate::{Combined, Source, Up2Stream, Up2StreamBuilder, Volume};
+
+    extern crate std;
+    use std::string::String;
+    use std::vec::Vec;
+
+    const SESSION: &str = "\
+0 TX ;
+1 TX VOL:20;
+2 TX VOL;
+3 RX VOL:20;
+4 TX SRC;
+5 RX SRC:NET;
+";
+
+    fn record_session() -> String {
+        let mut time = 0;
+        let clock = || {
+            time += 1;
+            time - 1
+        };
+
+        let recorder = Recorder::new(Simulator::new(), clock, TextLog(String::new()));
+        let mut up2stream_device = Up2Stream::new_combined(recorder);
+
+        up2stream_device
+            .set_volume(Volume::new(20).unwrap())
+            .unwrap();
+        assert_eq!(up2stream_device.volume().unwrap(), Volume::new(20).unwrap());
+        assert_eq!(up2stream_device.input_source().unwrap(), Source::Net);
+
+        let (recorder, _) = up2stream_device.release();
+        assert_eq!(recorder.lost_records(), 0);
+
+        let (_, TextLog(text)) = recorder.release();
+        text
+    }
+
+    #[test]
+    fn record_text() {
+        assert_eq!(record_session(), SESSION);
+    }
+
+    #[test]
+    fn text_escapes() {
+        let record = Record::new(1234, Direction::Rx, b"\r\nBOOT\\ \x00;").unwrap();
+        let text = std::format!("{}", record);
+
+        assert_eq!(text, "1234 RX \\x0D\\x0ABOOT\\\\ \\x00;");
+        assert_eq!(parse_text(&text).next().unwrap().unwrap(), record);
+    }
+
+    #[test]
+    fn text_invalid() {
+        for line in [
+            "1 TX",
+            "1 TX ",
+            "x TX VOL;",
+            "1 XX VOL;",
+            "1 RX VOL\\x4",
+            "1 RX VOL\\x4G",
+            "1 RX \\n",
+        ] {
+            assert!(
+                matches!(parse_text(line).next(), Some(Err(Error::InvalidTranscript))),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let mut buffer = [0; 256];
+        let mut log = BinaryLog::new(&mut buffer);
+
+        let records = [
+            Record::new(0, Direction::Tx, b";").unwrap(),
+            Record::new(100_000, Direction::Tx, b"VOL;").unwrap(),
+            Record::new(100_003, Direction::Rx, &[0xFF; MAX_RECORD_LENGTH]).unwrap(),
+        ];
+
+        for record in &records {
+            log.record(record).unwrap();
+        }
+
+        // Header, time and bytes
+        assert_eq!(
+            log.as_bytes().len(),
+            (1 + 1 + 1) + (1 + 3 + 4) + (1 + 1 + 128)
+        );
+
+        let decoded: Vec<Record> = decode_binary(log.as_bytes()).map(Result::unwrap).collect();
+        assert_eq!(decoded, records);
+    }
+
+    #[test]
+    fn binary_invalid() {
+        // Missing bytes of the record
+        let mut records = decode_binary(&[0x03, 0x01, b'V', b'O']);
+        assert!(matches!(
+            records.next(),
+            Some(Err(Error::InvalidTranscript))
+        ));
+        assert!(records.next().is_none());
+
+        // Time does not fit into u32
+        let mut records = decode_binary(&[0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, b';']);
+        assert!(matches!(
+            records.next(),
+            Some(Err(Error::InvalidTranscript))
+        ));
+    }
+
+    #[test]
+    fn binary_log_full() {
+        let mut buffer = [0; 8];
+        let mut log = BinaryLog::new(&mut buffer);
+
+        log.record(&Record::new(0, Direction::Tx, b"VOL;").unwrap())
+            .unwrap();
+        assert!(matches!(
+            log.record(&Record::new(1, Direction::Rx, b"VOL:1;").unwrap()),
+            Err(Error::BufferOverflow)
+        ));
+        assert_eq!(log.as_bytes(), b"\x03\x00VOL;");
+    }
+
+    #[test]
+    fn binary_log_empty_record() {
+        let mut buffer = [0; 8];
+        let mut log = BinaryLog::new(&mut buffer);
+
+        // The fields are public, so a record can be built without Record::new
+        let empty = Record {
+            time_ms: 0,
+            direction: Direction::Tx,
+            bytes: ArrayVec::new(),
+        };
+        assert!(matches!(log.record(&empty), Err(Error::InvalidTranscript)));
+        assert!(log.as_bytes().is_empty());
+    }
+
+    #[test]
+    fn recorder_counts_lost_records() {
+        let mut buffer = [0; 12];
+        let recorder = Recorder::new(Simulator::new(), || 0, BinaryLog::new(&mut buffer));
+        let mut up2stream_device = Up2Stream::new_combined(recorder);
+
+        up2stream_device.volume().unwrap();
+
+        let (recorder, _) = up2stream_device.release();
+        assert_eq!(recorder.lost_records(), 1);
+        assert_eq!(decode_binary(recorder.log().as_bytes()).count(), 2);
+    }
+
+    #[test]
+    fn replay() {
+        let text = record_session();
+        let mut up2stream_device = Up2Stream::new_combined(Replay::new(parse_text(&text)));
+
+        up2stream_device
+            .set_volume(Volume::new(20).unwrap())
+            .unwrap();
+        assert_eq!(up2stream_device.volume().unwrap(), Volume::new(20).unwrap());
+        assert_eq!(up2stream_device.input_source().unwrap(), Source::Net);
+
+        let (replay, _) = up2stream_device.release();
+        replay.finish().unwrap();
+    }
+
+    #[test]
+    fn replay_binary() {
+        let mut buffer = [0; 64];
+        let mut log = BinaryLog::new(&mut buffer);
+        for record in parse_text(SESSION) {
+            log.record(&record.unwrap()).unwrap();
+        }
+
+        let mut up2stream_device =
+            Up2Stream::new_combined(Replay::new(decode_binary(log.as_bytes())));
+
+        up2stream_device
+            .set_volume(Volume::new(20).unwrap())
+            .unwrap();
+        up2stream_device.volume().unwrap();
+        up2stream_device.input_source().unwrap();
+
+        let (replay, _) = up2stream_device.release();
+        replay.finish().unwrap();
+    }
+
+    #[test]
+    fn replay_flags_different_command() {
+        let mut up2stream_device = Up2Stream::new_combined(Replay::new(parse_text(SESSION)));
+
+        up2stream_device
+            .set_volume(Volume::new(21).unwrap())
+            .unwrap();
+        // The recorded response is still returned
+        assert_eq!(up2stream_device.volume().unwrap(), Volume::new(20).unwrap());
+
+        let (replay, _) = up2stream_device.release();
+        assert!(matches!(
+            replay.divergence(),
+            Some(Divergence::Sent {
+                record: 1,
+                offset: 5,
+                expected: b'0',
+                actual: b'1'
+            })
+        ));
+    }
+
+    #[test]
+    fn replay_flags_unexpected_query() {
+        let mut up2stream_device = Up2Stream::new_combined(Replay::new(parse_text("0 TX ;")));
+
+        // The end of the transcript is reported as a read error instead of blocking
+        assert!(matches!(up2stream_device.volume(), Err(Error::Read)));
+
+        let (replay, _) = up2stream_device.release();
+        assert!(matches!(
+            replay.finish(),
+            Err(Divergence::Unexpected {
+                record: 1,
+                actual: b'V'
+            })
+        ));
+    }
+
+    #[test]
+    fn replay_does_not_block_after_divergence() {
+        let mut up2stream_device = Up2StreamBuilder::new()
+            .retries(0)
+            .build(Replay::new(parse_text(SESSION)), Combined)
+            .unwrap();
+
+        // The recorded command is longer than the query, so the recorded response is never reached
+        assert!(matches!(up2stream_device.volume(), Err(Error::Read)));
+
+        let (replay, _) = up2stream_device.release();
+        assert!(matches!(
+            replay.divergence(),
+            Some(Divergence::Sent {
+                record: 1,
+                offset: 3,
+                expected: b':',
+                actual: b';'
+            })
+        ));
+    }
+
+    #[test]
+    fn replay_flags_incomplete_session() {
+        let mut up2stream_device = Up2Stream::new_combined(Replay::new(parse_text(SESSION)));
+
+        up2stream_device
+            .set_volume(Volume::new(20).unwrap())
+            .unwrap();
+
+        let (replay, _) = up2stream_device.release();
+        assert!(matches!(
+            replay.finish(),
+            Err(Divergence::Incomplete { record: 2 })
+        ));
+    }
+
+    #[test]
+    fn replay_flags_invalid_transcript() {
+        let mut up2stream_device =
+            Up2Stream::new_combined(Replay::new(parse_text("0 TX ;\n1 TX VOL;\n2 RX VOL:\\q;")));
+
+        assert!(up2stream_device.volume().is_err());
+
+        let (replay, _) = up2stream_device.release();
+        assert!(matches!(
+            replay.finish(),
+            Err(Divergence::InvalidTranscript {
+                record: 2,
+                error: Error::InvalidTranscript
+            })
+        ));
+    }
+}
@@ -0,0 +1,390 @@
+//! Conformance of the driver with synthetic transcripts of the serial traffic of the board.
+//!
+//! The transcripts are written by hand from the frames of the UART API description, they are not
+//! captures of a real board. Every directory in `tests/transcripts` holds one set of transcripts,
+//! see `tests/transcripts/README.md`. Each transcript is replayed while calling the public method of
+//! [Up2Stream] named by its `# call:` line, with the API version given by an optional `# protocol:`
+//! line, and the result is compared with its `# result:` line.
+//! The test fails with a report of all the differences, including the bytes sent by the driver that
+//! differ from the transcript, and of the methods for which a set has no transcript.
+
+use std::fmt::Debug;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use embedded_hal_mock::delay::MockNoop;
+use up2stream_uart::transcript::{parse_text, Divergence, Replay, TextRecords};
+use up2stream_uart::{
+    AudioChannel, Bass, Combined, Led, LoopMode, ProtocolVersion, Query, Source, Switch,
+    SwitchPauses, SystemControl, Treble, Up2Stream, Up2StreamBuilder, Volume,
+};
+
+type Driver<'a> = Up2Stream<Replay<TextRecords<'a>>, Combined>;
+
+/// Calls a method of the driver with the argument given in the transcript and formats the result.
+type Call = fn(&mut Driver, &str) -> String;
+
+const TRANSCRIPTS: &str = "tests/transcripts";
+const CALL: &str = "# call:";
+const RESULT: &str = "# result:";
+const PROTOCOL: &str = "# protocol:";
+
+/// All the public methods of the driver that communicate with the board.
+fn methods() -> Vec<(&'static str, Call)> {
+    vec![
+        ("firmware_version", |d, _| {
+            format!("{:?}", d.firmware_version())
+        }),
+        ("status", |d, _| format!("{:?}", d.status())),
+        ("execute_system_control", |d, a| {
+            format!("{:?}", d.execute_system_control(parse::<SystemControl>(a)))
+        }),
+        ("internet_connection", |d, _| {
+            format!("{:?}", d.internet_connection())
+        }),
+        ("audio_out", |d, _| format!("{:?}", d.audio_out())),
+        ("set_audio_out", |d, a| {
+            format!("{:?}", d.set_audio_out(parse::<bool>(a)))
+        }),
+        ("input_source", |d, _| format!("{:?}", d.input_source())),
+        ("select_input_source", |d, a| {
+            format!("{:?}", d.select_input_source(parse::<Source>(a)))
+        }),
+        ("volume", |d, _| format!("{:?}", d.volume())),
+        ("set_volume", |d, a| {
+            format!("{:?}", d.set_volume(parse::<Volume>(a)))
+        }),
+        ("mute_status", |d, _| format!("{:?}", d.mute_status())),
+        ("set_mute", |d, a| {
+            format!("{:?}", d.set_mute(parse::<Switch>(a)))
+        }),
+        ("bass", |d, _| format!("{:?}", d.bass())),
+        ("set_bass", |d, a| {
+            format!("{:?}", d.set_bass(parse::<Bass>(a)))
+        }),
+        ("treble", |d, _| format!("{:?}", d.treble())),
+        ("set_treble", |d, a| {
+            format!("{:?}", d.set_treble(parse::<Treble>(a)))
+        }),
+        ("play_pause_toggle", |d, _| {
+            format!("{:?}", d.play_pause_toggle())
+        }),
+        ("stop", |d, _| format!("{:?}", d.stop())),
+        ("next_track", |d, _| format!("{:?}", d.next_track())),
+        ("previous_track", |d, _| format!("{:?}", d.previous_track())),
+        ("bluetooth_connected", |d, _| {
+            format!("{:?}", d.bluetooth_connected())
+        }),
+        ("connect_bluetooth", |d, _| {
+            format!("{:?}", d.connect_bluetooth())
+        }),
+        ("disconnect_bluetooth", |d, _| {
+            format!("{:?}", d.disconnect_bluetooth())
+        }),
+        ("led", |d, _| format!("{:?}", d.led())),
+        ("set_led", |d, a| {
+            format!("{:?}", d.set_led(parse::<Led>(a)))
+        }),
+        ("audio_channel", |d, _| format!("{:?}", d.audio_channel())),
+        ("set_audio_channel", |d, a| {
+            format!("{:?}", d.set_audio_channel(parse::<AudioChannel>(a)))
+        }),
+        ("beep", |d, _| format!("{:?}", d.beep())),
+        ("set_beep", |d, a| {
+            format!("{:?}", d.set_beep(parse::<bool>(a)))
+        }),
+        ("virtual_bass", |d, _| format!("{:?}", d.virtual_bass())),
+        ("enable_virtual_bass", |d, _| {
+            format!("{:?}", d.enable_virtual_bass())
+        }),
+        ("disable_virtual_bass", |d, _| {
+            format!("{:?}", d.disable_virtual_bass())
+        }),
+        ("toggle_virtual_bass", |d, _| {
+            format!("{:?}", d.toggle_virtual_bass())
+        }),
+        ("loop_mode", |d, _| format!("{:?}", d.loop_mode())),
+        ("set_loop_mode", |d, a| {
+            format!("{:?}", d.set_loop_mode(parse::<LoopMode>(a)))
+        }),
+        ("query_many", |d, a| {
+            format!("{:?}", d.query_many(&queries::<2>(a)))
+        }),
+        ("raw_command", |d, a| {
+            let mut words = a.split_whitespace();
+            let name = words.next().unwrap_or_default();
+            let parameters: Vec<&str> = words.collect();
+            format!("{:?}", d.raw_command(name, &parameters))
+        }),
+        ("raw_query", |d, a| format!("{:?}", d.raw_query(a))),
+        ("volume_up", |d, a| format!("{:?}", d.volume_up(parse(a)))),
+        ("volume_down", |d, a| {
+            format!("{:?}", d.volume_down(parse(a)))
+        }),
+        ("bass_up", |d, a| format!("{:?}", d.bass_up(parse(a)))),
+        ("bass_down", |d, a| format!("{:?}", d.bass_down(parse(a)))),
+        ("treble_up", |d, a| format!("{:?}", d.treble_up(parse(a)))),
+        ("treble_down", |d, a| {
+            format!("{:?}", d.treble_down(parse(a)))
+        }),
+        ("ramp_volume", |d, a| {
+            let (target, duration_ms) = a.split_once(' ').unwrap_or((a, ""));
+            format!(
+                "{:?}",
+                d.ramp_volume(
+                    parse::<Volume>(target),
+                    parse(duration_ms),
+                    &mut MockNoop::new(),
+                    || false
+                )
+            )
+        }),
+        ("fade_out", |d, a| {
+            format!("{:?}", d.fade_out(parse(a), &mut MockNoop::new(), || false))
+        }),
+        ("fade_in", |d, a| {
+            let (target, duration_ms) = a.split_once(' ').unwrap_or((a, ""));
+            format!(
+                "{:?}",
+                d.fade_in(
+                    parse::<Volume>(target),
+                    parse(duration_ms),
+                    &mut MockNoop::new(),
+                    || false
+                )
+            )
+        }),
+        ("switch_source_smoothly", |d, a| {
+            format!(
+                "{:?}",
+                d.switch_source_smoothly(
+                    parse::<Source>(a),
+                    &SwitchPauses::default(),
+                    &mut MockNoop::new()
+                )
+            )
+        }),
+    ]
+}
+
+fn parse<T>(argument: &str) -> T
+where
+    T: FromStr,
+    T::Err: Debug,
+{
+    argument
+        .parse()
+        .unwrap_or_else(|e| panic!("invalid argument {:?}: {:?}", argument, e))
+}
+
+/// The `Q` queries named in `argument`, e.g. `Volume Bass`.
+fn queries<const Q: usize>(argument: &str) -> [Query; Q] {
+    let queries: Vec<Query> = argument
+        .split_whitespace()
+        .map(|name| match name {
+            "Status" => Query::Status,
+            "InternetConnection" => Query::InternetConnection,
+            "AudioOut" => Query::AudioOut,
+            "InputSource" => Query::InputSource,
+            "Volume" => Query::Volume,
+            "Mute" => Query::Mute,
+            "Bass" => Query::Bass,
+            "Treble" => Query::Treble,
+            _ => panic!("invalid query {:?}", name),
+        })
+        .collect();
+
+    queries
+        .try_into()
+        .unwrap_or_else(|queries| panic!("expected {} queries: {:?}", Q, queries))
+}
+
+fn protocol_version(argument: &str) -> Result<ProtocolVersion, String> {
+    match argument {
+        "V3" => Ok(ProtocolVersion::V3),
+        "V4" => Ok(ProtocolVersion::V4),
+        _ => Err(format!("invalid protocol version {:?}", argument)),
+    }
+}
+
+struct Transcript {
+    path: PathBuf,
+    text: String,
+    method: String,
+    argument: String,
+    result: String,
+    protocol_version: ProtocolVersion,
+}
+
+impl Transcript {
+    fn load(path: PathBuf) -> Result<Transcript, String> {
+        let text = fs::read_to_string(&path)
+            .map_err(|e| format!("{}: cannot be read: {}\n", path.display(), e))?;
+
+        let optional_directive = |name: &str| {
+            text.lines()
+                .find_map(|line| line.strip_prefix(name))
+                .map(|value| value.trim().to_string())
+        };
+        let directive = |name: &str| {
+            optional_directive(name).ok_or(format!("{}: has no `{}` line\n", path.display(), name))
+        };
+
+        let call = directive(CALL)?;
+        let (method, argument) = call.split_once(' ').unwrap_or((&call, ""));
+        let protocol_version = match optional_directive(PROTOCOL) {
+            Some(version) => {
+                protocol_version(&version).map_err(|e| format!("{}: {}\n", path.display(), e))?
+            }
+            None => ProtocolVersion::default(),
+        };
+
+        Ok(Transcript {
+            method: method.to_string(),
+            argument: argument.to_string(),
+            result: directive(RESULT)?,
+            protocol_version,
+            path,
+            text,
+        })
+    }
+
+    /// Replay the transcript, returning a report of the differences.
+    fn check(&self, call: Call) -> Option<String> {
+        let mut driver = Up2StreamBuilder::new()
+            .protocol_version(self.protocol_version)
+            .build(Replay::new(parse_text(&self.text)), Combined)
+            .expect("writing to a replay cannot fail");
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| call(&mut driver, &self.argument)))
+            .unwrap_or_else(|_| String::from("panicked"));
+
+        let (replay, _) = driver.release();
+        let divergence = replay.finish().err();
+
+        if result == self.result && divergence.is_none() {
+            return None;
+        }
+
+        let mut report = format!(
+            "{}: {} {}\n",
+            self.path.display(),
+            self.method,
+            self.argument
+        );
+        if result != self.result {
+            report += &format!("  - result: {}\n  + result: {}\n", self.result, result);
+        }
+        if let Some(divergence) = divergence {
+            report += &format!("  {}\n", describe(&divergence));
+        }
+        Some(report)
+    }
+}
+
+fn describe(divergence: &Divergence) -> String {
+    let byte = |b: &u8| format!("'{}'", b.escape_ascii());
+
+    match divergence {
+        Divergence::Sent {
+            record,
+            offset,
+            expected,
+            actual,
+        } => format!(
+            "sent {} instead of {} at byte {} of record {}",
+            byte(actual),
+            byte(expected),
+            offset,
+            record
+        ),
+        Divergence::Unexpected { record, actual } => {
+            format!("sent unexpected {} at record {}", byte(actual), record)
+        }
+        Divergence::Incomplete { record } => {
+            format!(
+                "stopped before the end of the transcript at record {}",
+                record
+            )
+        }
+        Divergence::InvalidTranscript { record, error } => {
+            format!("invalid record {}: {:?}", record, error)
+        }
+    }
+}
+
+fn sorted_entries(directory: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(directory)
+        .unwrap_or_else(|e| panic!("cannot read {}: {}", directory.display(), e))
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort();
+    entries
+}
+
+#[test]
+fn synthetic_transcripts() {
+    let methods = methods();
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join(TRANSCRIPTS);
+
+    let transcript_sets: Vec<PathBuf> = sorted_entries(&root)
+        .into_iter()
+        .filter(|path| path.is_dir())
+        .collect();
+    assert!(
+        !transcript_sets.is_empty(),
+        "no transcripts found in {}",
+        root.display()
+    );
+
+    let mut failures = Vec::new();
+
+    for transcript_set in &transcript_sets {
+        let mut covered = Vec::new();
+
+        for path in sorted_entries(transcript_set) {
+            if path.extension() != Some("txt".as_ref()) {
+                continue;
+            }
+
+            let transcript = match Transcript::load(path) {
+                Ok(transcript) => transcript,
+                Err(report) => {
+                    failures.push(report);
+                    continue;
+                }
+            };
+
+            match methods.iter().find(|(name, _)| *name == transcript.method) {
+                Some((name, call)) => {
+                    covered.push(*name);
+                    failures.extend(transcript.check(*call));
+                }
+                None => failures.push(format!(
+                    "{}: unknown method `{}`\n",
+                    transcript.path.display(),
+                    transcript.method
+                )),
+            }
+        }
+
+        for (name, _) in &methods {
+            if !covered.contains(name) {
+                failures.push(format!(
+                    "{}: no transcript for `{}`\n",
+                    transcript_set.display(),
+                    name
+                ));
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} transcript failures:\n\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}